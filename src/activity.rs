@@ -0,0 +1,291 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+
+use crate::{
+    config::ActivityConfig, error::DyricsError, playback::CurrentPlayback, sink::StatusSink,
+    text::truncate,
+};
+
+const OP_HANDSHAKE: u32 = 0;
+const OP_FRAME: u32 = 1;
+
+/// Discord caps activity text fields at this many characters.
+const MAX_FIELD_LEN: usize = 128;
+
+/// How far the position may be off from where it should be by now before it
+/// counts as a seek.
+const SEEK_TOLERANCE: Duration = Duration::from_secs(2);
+
+trait IpcStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> IpcStream for T {}
+
+#[cfg(unix)]
+async fn connect() -> Option<Box<dyn IpcStream>> {
+    let dir = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok())
+        .unwrap_or_else(|| "/tmp".to_string());
+
+    for index in 0..10 {
+        let path = format!("{}/discord-ipc-{}", dir.trim_end_matches('/'), index);
+        if let Ok(stream) = tokio::net::UnixStream::connect(path).await {
+            return Some(Box::new(stream));
+        }
+    }
+    None
+}
+
+#[cfg(windows)]
+async fn connect() -> Option<Box<dyn IpcStream>> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+
+    for index in 0..10 {
+        let path = format!(r"\\.\pipe\discord-ipc-{}", index);
+        if let Ok(pipe) = ClientOptions::new().open(path) {
+            return Some(Box::new(pipe));
+        }
+    }
+    None
+}
+
+/// Unix timestamps (in milliseconds) for Discord's activity progress bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActivityTimestamps {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ActivityTimestamps {
+    pub fn new(now: SystemTime, position: Duration, duration: Duration) -> Self {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let start = now.saturating_sub(position);
+
+        Self {
+            start: start.as_millis() as u64,
+            end: (start + duration).as_millis() as u64,
+        }
+    }
+}
+
+struct Progress {
+    generation: u64,
+    track: String,
+    position: Duration,
+    duration: Duration,
+    observed_at: Instant,
+}
+
+/// Shows the status as a Rich Presence activity through the local Discord
+/// client, including a progress bar for the track.
+pub struct ActivitySink {
+    client_id: String,
+    stream: Option<Box<dyn IpcStream>>,
+    progress: Option<Progress>,
+    last_sent: Option<String>,
+    nonce: u64,
+    warned: bool,
+}
+
+impl ActivitySink {
+    pub fn new(config: &ActivityConfig) -> Self {
+        Self {
+            client_id: config.client_id.clone(),
+            stream: None,
+            progress: None,
+            last_sent: None,
+            nonce: 0,
+            warned: false,
+        }
+    }
+
+    async fn write_frame(
+        stream: &mut Box<dyn IpcStream>,
+        op: u32,
+        payload: &Value,
+    ) -> Result<Value, DyricsError> {
+        let payload = serde_json::to_vec(payload).map_err(std::io::Error::from)?;
+
+        let mut frame = Vec::with_capacity(8 + payload.len());
+        frame.extend_from_slice(&op.to_le_bytes());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        stream.write_all(&frame).await?;
+
+        let mut header = [0; 8];
+        stream.read_exact(&mut header).await?;
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let mut body = vec![0; len as usize];
+        stream.read_exact(&mut body).await?;
+
+        Ok(serde_json::from_slice(&body).map_err(std::io::Error::from)?)
+    }
+
+    /// Connects and handshakes with the Discord client if not done already.
+    /// Returns `false` if Discord isn't running.
+    async fn ensure_connected(&mut self) -> Result<bool, DyricsError> {
+        if self.stream.is_some() {
+            return Ok(true);
+        }
+
+        let Some(mut stream) = connect().await else {
+            if !self.warned {
+                tracing::warn!("Could not find a running Discord client for the activity status");
+                self.warned = true;
+            }
+            return Ok(false);
+        };
+
+        Self::write_frame(
+            &mut stream,
+            OP_HANDSHAKE,
+            &json!({ "v": 1, "client_id": self.client_id }),
+        )
+        .await?;
+        self.stream = Some(stream);
+        self.warned = false;
+
+        Ok(true)
+    }
+
+    async fn set_activity(&mut self, activity: Value) -> Result<bool, DyricsError> {
+        if !self.ensure_connected().await? {
+            return Ok(false);
+        }
+
+        self.nonce += 1;
+        let payload = json!({
+            "cmd": "SET_ACTIVITY",
+            "args": {
+                "pid": std::process::id(),
+                "activity": activity,
+            },
+            "nonce": self.nonce.to_string(),
+        });
+
+        let stream = self.stream.as_mut().expect("connected above");
+        if let Err(err) = Self::write_frame(stream, OP_FRAME, &payload).await {
+            // reconnect on the next update
+            self.stream = None;
+            return Err(err);
+        }
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl StatusSink for ActivitySink {
+    fn name(&self) -> &str {
+        "activity"
+    }
+
+    fn set_playback(&mut self, playback: &CurrentPlayback) {
+        let jumped = self.progress.as_ref().is_some_and(|progress| {
+            let expected = progress.position + progress.observed_at.elapsed();
+            progress.generation != playback.generation
+                || playback.position + SEEK_TOLERANCE < expected
+                || playback.position > expected + SEEK_TOLERANCE
+        });
+        // the line may still be the same, but the progress bar is off now
+        if jumped {
+            self.last_sent = None;
+        }
+
+        self.progress = Some(Progress {
+            generation: playback.generation,
+            track: playback.track.name.clone(),
+            position: playback.position,
+            duration: playback.duration,
+            observed_at: Instant::now(),
+        });
+    }
+
+    async fn send_update(&mut self, text: &str, _emoji: &str) -> Result<bool, DyricsError> {
+        if self.last_sent.as_deref() == Some(text) {
+            return Ok(false);
+        }
+
+        let mut activity = json!({ "state": truncate(text, MAX_FIELD_LEN) });
+        if let Some(ref progress) = self.progress {
+            // computed at send time so the bar lines up with the line being shown
+            let position = progress.position + progress.observed_at.elapsed();
+            let timestamps =
+                ActivityTimestamps::new(SystemTime::now(), position, progress.duration);
+
+            activity["details"] = json!(truncate(&progress.track, MAX_FIELD_LEN));
+            activity["timestamps"] = json!({
+                "start": timestamps.start,
+                "end": timestamps.end,
+            });
+        }
+
+        let sent = self.set_activity(activity).await?;
+        if sent {
+            self.last_sent = Some(text.to_string());
+        }
+
+        Ok(sent)
+    }
+
+    async fn clear_status(&mut self) -> Result<(), DyricsError> {
+        self.progress = None;
+        if self.last_sent.take().is_none() {
+            return Ok(());
+        }
+
+        self.set_activity(Value::Null).await.map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn playback(position: u64) -> CurrentPlayback {
+        let track = serde_json::from_value(json!({
+            "album": { "name": "Album", "artists": [], "external_urls": {}, "images": [] },
+            "artists": [],
+            "disc_number": 1,
+            "duration_ms": 180000,
+            "explicit": false,
+            "external_ids": {},
+            "external_urls": {},
+            "is_local": false,
+            "name": "Song",
+            "popularity": 0,
+            "track_number": 1
+        }))
+        .unwrap();
+        CurrentPlayback {
+            lyrics: None,
+            track,
+            position: Duration::from_secs(position),
+            duration: Duration::from_secs(180),
+            context: None,
+            generation: 1,
+            synced_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn seeking_resends_the_line_for_the_progress_bar() {
+        let mut sink = ActivitySink::new(&ActivityConfig {
+            client_id: "123".to_string(),
+        });
+        sink.set_playback(&playback(10));
+        sink.last_sent = Some("a line".to_string());
+
+        // about where it should be by now
+        sink.set_playback(&playback(11));
+        assert_eq!(sink.last_sent.as_deref(), Some("a line"));
+
+        sink.set_playback(&playback(60));
+        assert_eq!(sink.last_sent, None);
+    }
+}
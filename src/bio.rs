@@ -0,0 +1,104 @@
+use std::time::Instant;
+
+use async_trait::async_trait;
+use reqwest::Client;
+
+use crate::{
+    config::{BioConfig, DiscordConfig},
+    discord::DiscordApi,
+    error::DyricsError,
+    sink::{prepend_emoji, RequestWindow, StatusSink},
+    text::truncate,
+};
+
+/// Discord refuses "About Me"s longer than this many characters.
+pub const MAX_BIO_LEN: usize = 190;
+
+/// Shows the lyrics in the "About Me" of a Discord account. Profile edits are
+/// limited a lot more than status updates, so lines are skipped while it is
+/// out of capacity. Whatever the bio said before is put back once cleared.
+pub struct BioSink {
+    name: String,
+    api: DiscordApi,
+    max_len: usize,
+    prepend_emoji: bool,
+    requests: RequestWindow,
+    /// The bio from before dyrics touched it
+    original: Option<String>,
+    last_sent: Option<String>,
+}
+
+impl BioSink {
+    pub fn new(
+        client: Client,
+        discord: &DiscordConfig,
+        config: &BioConfig,
+        token: &str,
+        account: Option<usize>,
+    ) -> Self {
+        Self {
+            name: match account {
+                Some(account) => format!("bio account {}", account),
+                None => "bio".to_string(),
+            },
+            api: DiscordApi::new(client, discord, token),
+            max_len: config.max_len.min(MAX_BIO_LEN),
+            prepend_emoji: config.prepend_emoji,
+            requests: RequestWindow::new(config.rate_limit, config.rate_limit_window),
+            original: None,
+            last_sent: None,
+        }
+    }
+}
+
+#[async_trait]
+impl StatusSink for BioSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn send_update(&mut self, text: &str, emoji: &str) -> Result<bool, DyricsError> {
+        if self.last_sent.as_deref() == Some(text) || !self.requests.has_capacity() {
+            return Ok(false);
+        }
+
+        if self.original.is_none() {
+            self.original = Some(self.api.bio().await?);
+        }
+
+        let bio = if self.prepend_emoji {
+            prepend_emoji(text, emoji)
+        } else {
+            text.to_string()
+        };
+        self.requests.record(Instant::now());
+        self.api.set_bio(&truncate(&bio, self.max_len)).await?;
+        self.last_sent = Some(text.to_string());
+
+        Ok(true)
+    }
+
+    async fn clear_status(&mut self) -> Result<(), DyricsError> {
+        let Some(ref original) = self.original else {
+            return Ok(());
+        };
+
+        // putting the bio back is worth going over the limit for
+        self.requests.record(Instant::now());
+        if let Err(err) = self.api.set_bio(original).await {
+            // the sink is dropped over an invalid token, so this was the last try
+            if matches!(err, DyricsError::DiscordAuth(_)) {
+                tracing::error!(
+                    "The {} could not be put back, it was: {}",
+                    self.name,
+                    original
+                );
+            }
+            return Err(err);
+        }
+        self.original = None;
+        self.last_sent = None;
+
+        Ok(())
+    }
+}
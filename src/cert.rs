@@ -0,0 +1,70 @@
+//! A throwaway self-signed certificate for the https callback server.
+//!
+//! Spotify only redirects to the exact `redirect_uri`, so an `https://`
+//! loopback uri needs a server that speaks TLS. The certificate is made up
+//! on every login and never stored, browsers will warn about it once.
+
+use std::net::IpAddr;
+
+use eyre::WrapErr as _;
+use rcgen::{CertificateParams, DistinguishedName, DnType, KeyPair, SanType};
+use time::{Duration, OffsetDateTime};
+
+/// A DER encoded certificate and its PKCS#8 private key.
+pub struct SelfSigned {
+    pub cert: Vec<u8>,
+    pub key: Vec<u8>,
+}
+
+/// Makes a certificate for `ip` and `localhost` that is valid for a day.
+pub fn self_signed(ip: IpAddr) -> eyre::Result<SelfSigned> {
+    let failed = "failed to generate a certificate for the callback server";
+
+    let key_pair = KeyPair::generate().wrap_err(failed)?;
+
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]).wrap_err(failed)?;
+    params.subject_alt_names.insert(0, SanType::IpAddress(ip));
+    params.distinguished_name = DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, "dyrics");
+    let now = OffsetDateTime::now_utc();
+    params.not_before = now - Duration::hours(1);
+    params.not_after = now + Duration::days(1);
+
+    let cert = params.self_signed(&key_pair).wrap_err(failed)?;
+
+    Ok(SelfSigned {
+        cert: cert.der().to_vec(),
+        key: key_pair.serialize_der(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use x509_parser::{extensions::GeneralName, prelude::*};
+
+    use super::*;
+
+    #[test]
+    fn covers_the_callback_address_for_a_day() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let signed = self_signed(ip).unwrap();
+        let (rest, cert) = X509Certificate::from_der(&signed.cert).unwrap();
+        assert!(rest.is_empty());
+
+        let alt_names = cert.subject_alternative_name().unwrap().unwrap();
+        assert_eq!(
+            alt_names.value.general_names,
+            vec![
+                GeneralName::IPAddress(&[127, 0, 0, 1]),
+                GeneralName::DNSName("localhost"),
+            ]
+        );
+
+        let validity = cert.validity();
+        assert!(validity.is_valid());
+        let lifetime = validity.not_after.timestamp() - validity.not_before.timestamp();
+        assert_eq!(lifetime, 25 * 60 * 60);
+    }
+}
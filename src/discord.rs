@@ -0,0 +1,1168 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::{header::DATE, Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use serde_with::{serde_as, DurationMilliSeconds};
+
+use crate::{
+    config::{ClearPayload, DiscordConfig, TokenType},
+    error::DyricsError,
+    file::write_atomic,
+    recorder::{Event, Recorder},
+    sink::{RequestWindow, StatusSink},
+    text::MAX_STATUS_LEN,
+};
+
+/// Latency estimates are capped at this, anything slower is an outlier.
+const MAX_LATENCY: Duration = Duration::from_secs(1);
+
+/// Longest the clear on shutdown waits for the rate limit.
+const SHUTDOWN_CLEAR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the state file is written while requests are being sent.
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The accounts share the state file, so only one of them may update it at
+/// a time.
+static STATE_FILE_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Builds the HTTP client Discord requests are sent through.
+pub fn http_client(config: &DiscordConfig) -> Result<Client, DyricsError> {
+    let mut builder = Client::builder().timeout(config.http_timeout);
+    if let Some(ref proxy) = config.http_proxy {
+        builder = builder.proxy(proxy.to_proxy()?);
+    }
+    builder.build().map_err(DyricsError::Discord)
+}
+
+/// The authorization header for `token`. Tokens are often pasted with the
+/// prefix of another kind of token, or with one where none belongs.
+pub fn authorization(token: &str, token_type: TokenType) -> String {
+    let token = token.trim();
+    let (prefix, bare) = ["Bearer ", "Bot "]
+        .into_iter()
+        .find_map(|prefix| {
+            token
+                .get(..prefix.len())
+                .filter(|start| start.eq_ignore_ascii_case(prefix))
+                .map(|_| (Some(prefix), token[prefix.len()..].trim_start()))
+        })
+        .unwrap_or((None, token));
+
+    match token_type {
+        TokenType::Auto if prefix == Some("Bot ") => format!("Bot {}", bare),
+        TokenType::Auto | TokenType::User => bare.to_string(),
+        TokenType::Bot => format!("Bot {}", bare),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiscordApi {
+    client: Client,
+    base_url: String,
+    token: String,
+    api_version: u8,
+}
+
+impl DiscordApi {
+    pub fn new(client: Client, config: &DiscordConfig, token: &str) -> Self {
+        Self {
+            client,
+            base_url: "https://discord.com/api".to_string(),
+            token: authorization(token, config.token_type),
+            api_version: config.api_version,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/v{}/{}", self.base_url, self.api_version, path)
+    }
+
+    /// Patches the user settings. Returns the server time from the response's
+    /// date header, if it had a usable one, along with the updated settings.
+    pub async fn patch_settings(
+        &self,
+        payload: &Value,
+    ) -> Result<(Option<SystemTime>, Value), DyricsError> {
+        self.patch("users/@me/settings", payload).await
+    }
+
+    /// Checks that the token works without changing anything.
+    pub async fn check_token(&self) -> Result<(), DyricsError> {
+        self.settings().await?;
+        Ok(())
+    }
+
+    async fn settings(&self) -> Result<Value, DyricsError> {
+        let response = self
+            .client
+            .get(self.url("users/@me/settings"))
+            .header("authorization", &self.token)
+            .send()
+            .await
+            .map_err(DyricsError::Discord)?;
+        check_status(response)?
+            .json()
+            .await
+            .map_err(DyricsError::Discord)
+    }
+
+    /// The custom status as Discord has it, `null` if there is none. Can be
+    /// set again as it is with [`Self::restore_status`].
+    pub async fn custom_status(&self) -> Result<Value, DyricsError> {
+        let mut settings = self.settings().await?;
+        Ok(settings
+            .get_mut("custom_status")
+            .map(Value::take)
+            .unwrap_or_default())
+    }
+
+    pub async fn restore_status(&self, status: &Value) -> Result<(), DyricsError> {
+        self.patch_settings(&json!({ "custom_status": status }))
+            .await?;
+        Ok(())
+    }
+
+    /// The "About Me" of the account.
+    pub async fn bio(&self) -> Result<String, DyricsError> {
+        let response = self
+            .client
+            .get(self.url("users/@me"))
+            .header("authorization", &self.token)
+            .send()
+            .await
+            .map_err(DyricsError::Discord)?;
+        let user = check_status(response)?
+            .json::<Value>()
+            .await
+            .map_err(DyricsError::Discord)?;
+
+        Ok(user
+            .get("bio")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    pub async fn set_bio(&self, bio: &str) -> Result<(), DyricsError> {
+        self.patch("users/@me", &json!({ "bio": bio })).await?;
+        Ok(())
+    }
+
+    async fn patch(
+        &self,
+        path: &str,
+        payload: &Value,
+    ) -> Result<(Option<SystemTime>, Value), DyricsError> {
+        let response = self
+            .client
+            .patch(self.url(path))
+            .header("authorization", &self.token)
+            .json(payload)
+            .send()
+            .await
+            .map_err(DyricsError::Discord)?;
+
+        let response = check_status(response)?;
+        let server_time = response
+            .headers()
+            .get(DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+            .map(SystemTime::from);
+        let settings = response.json().await.unwrap_or_default();
+
+        Ok((server_time, settings))
+    }
+
+    /// Sets the custom status, which Discord removes by itself at `expires_at`.
+    /// Returns the server time, see [`Self::patch_settings`].
+    pub async fn send_status(
+        &self,
+        text: &str,
+        emoji: &str,
+        expires_at: Option<SystemTime>,
+    ) -> Result<Option<SystemTime>, DyricsError> {
+        let (server_time, _) = self
+            .patch_settings(&status_payload(text, emoji, expires_at))
+            .await?;
+        Ok(server_time)
+    }
+
+    pub async fn clear_status(&self, shape: ClearPayload) -> Result<(), DyricsError> {
+        let (_, settings) = self.patch_settings(&clear_payload(shape)).await?;
+
+        let still_set = settings
+            .get("custom_status")
+            .and_then(|status| status.get("text"))
+            .and_then(Value::as_str)
+            .is_some_and(|text| !text.is_empty());
+        if still_set {
+            tracing::warn!(
+                "Discord kept the custom status after clearing it, try a different discord.clear_payload"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Turns error statuses into errors, telling an invalid token apart.
+fn check_status(response: Response) -> Result<Response, DyricsError> {
+    match response.status() {
+        status @ (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+            Err(DyricsError::DiscordAuth(status))
+        }
+        _ => response.error_for_status().map_err(DyricsError::Discord),
+    }
+}
+
+/// An emoji as Discord wants it in a custom status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emoji<'a> {
+    /// A plain unicode emoji like 🎶
+    Unicode(&'a str),
+    /// A server's own emoji, written as `name:id` or `<:name:id>`, and
+    /// `a:name:id` or `<a:name:id>` if it is animated
+    Custom {
+        name: &'a str,
+        id: &'a str,
+        animated: bool,
+    },
+}
+
+impl<'a> Emoji<'a> {
+    pub fn parse(emoji: &'a str) -> Self {
+        let trimmed = emoji.trim();
+        let bare = trimmed
+            .strip_prefix('<')
+            .and_then(|bare| bare.strip_suffix('>'))
+            .unwrap_or(trimmed);
+        // "a:name:id" is animated, while "a:id" is a static emoji called "a"
+        let (animated, bare) = match bare.strip_prefix("a:") {
+            Some(rest) if rest.contains(':') => (true, rest),
+            _ => (false, bare.strip_prefix(':').unwrap_or(bare)),
+        };
+
+        match bare.rsplit_once(':') {
+            Some((name, id))
+                if !name.is_empty() && !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                Emoji::Custom { name, id, animated }
+            }
+            _ => Emoji::Unicode(emoji),
+        }
+    }
+}
+
+pub fn status_payload(text: &str, emoji: &str, expires_at: Option<SystemTime>) -> Value {
+    // discord wants either the name or the id, depending on the kind of emoji
+    let mut payload = match Emoji::parse(emoji) {
+        Emoji::Unicode(name) => json!({
+            "custom_status": {
+                "text": text,
+                "emoji_name": name
+            }
+        }),
+        Emoji::Custom { id, animated, .. } => json!({
+            "custom_status": {
+                "text": text,
+                "emoji_id": id,
+                "emoji_name": null,
+                "animated": animated
+            }
+        }),
+    };
+    // an emoji on its own, an empty text next to it shows as an empty status
+    // in some clients
+    if text.is_empty() && !emoji.is_empty() {
+        if let Some(status) = payload["custom_status"].as_object_mut() {
+            status.remove("text");
+        }
+    }
+    if let Some(expires_at) = expires_at {
+        payload["custom_status"]["expires_at"] = DateTime::<Utc>::from(expires_at)
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+            .into();
+    }
+    payload
+}
+
+/// Appends `suffix` to `text` if both fit in `max_len` characters. The lyrics
+/// matter more, so the suffix is left out if they don't. A status that is
+/// only an emoji stays that way.
+pub fn with_suffix(text: &str, suffix: Option<&str>, max_len: usize) -> String {
+    match suffix {
+        Some(suffix)
+            if !text.is_empty() && text.chars().count() + suffix.chars().count() <= max_len =>
+        {
+            format!("{}{}", text, suffix)
+        }
+        _ => text.to_string(),
+    }
+}
+
+pub fn clear_payload(shape: ClearPayload) -> Value {
+    match shape {
+        ClearPayload::Null => json!({ "custom_status": null }),
+        ClearPayload::Empty => status_payload("", "", None),
+    }
+}
+
+/// Estimates how long a request took to reach Discord. Falls back to half the
+/// round trip if the server time can't narrow it down.
+pub fn estimate_latency(
+    sent_at: SystemTime,
+    round_trip: Duration,
+    server_time: Option<SystemTime>,
+) -> Duration {
+    let from_server_time = server_time.and_then(|server_time| {
+        // the date header only has second precision, so the request was
+        // handled somewhere within [server_time, server_time + 1s)
+        let earliest = server_time.duration_since(sent_at).unwrap_or_default();
+        let latest = (server_time + Duration::from_secs(1))
+            .duration_since(sent_at)
+            .ok()?
+            .min(round_trip);
+
+        // an empty range means our clock is off from Discord's
+        (earliest <= latest).then(|| (earliest + latest) / 2)
+    });
+
+    from_server_time.unwrap_or(round_trip / 2).min(MAX_LATENCY)
+}
+
+/// Whether updates are going through, see [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Updates are sent as usual
+    Closed,
+    /// Too many updates failed in a row, nothing is sent until `until`
+    Open { until: Instant },
+    /// The cooldown is over, the next update decides whether it opens again
+    HalfOpen,
+}
+
+/// Stops sending updates for a while once Discord keeps failing, instead of
+/// trying again on every loop.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            failures: 0,
+            open_until: None,
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        match self.open_until {
+            None => BreakerState::Closed,
+            Some(until) if Instant::now() < until => BreakerState::Open { until },
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    /// How many updates failed in a row.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn allows_request(&self) -> bool {
+        !matches!(self.state(), BreakerState::Open { .. })
+    }
+
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+        self.open_until = None;
+    }
+
+    /// Returns `true` if this failure opened the breaker.
+    pub fn record_failure(&mut self) -> bool {
+        self.failures += 1;
+        // a failed attempt while half open opens it right away again
+        if self.failures >= self.threshold {
+            self.open_until = Some(Instant::now() + self.cooldown);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// What a [`RateLimiter`] remembers across restarts. `Instant`s only mean
+/// something within one run, so requests are kept as their age at `saved_at`.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimiterState {
+    pub saved_at: SystemTime,
+    #[serde_as(as = "Vec<DurationMilliSeconds<u64>>")]
+    pub request_ages: Vec<Duration>,
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub latency_estimate: Duration,
+}
+
+/// The state file holds every account's [`RateLimiterState`] by sink name.
+async fn read_state_file(path: &Path) -> Result<HashMap<String, RateLimiterState>, DyricsError> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|err| {
+            DyricsError::Config(format!(
+                "{} is not a valid state file: {}",
+                path.display(),
+                err
+            ))
+        }),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Why [`RateLimiter`] didn't send an update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SkipReason {
+    /// The text is already showing
+    Duplicate,
+    /// Updates are paused after failing too often
+    Paused,
+    /// The rate limit is used up for now
+    RateLimited,
+}
+
+/// Sends status updates to Discord while staying within its rate limit.
+/// Rate limits are per account, so each token gets its own.
+pub struct RateLimiter {
+    name: String,
+    api: DiscordApi,
+    clear_payload: ClearPayload,
+    expire_status: bool,
+    expires_at: Option<SystemTime>,
+    /// Expiry of the status that was sent last
+    last_expires_at: Option<SystemTime>,
+    requests: RequestWindow,
+    last_sent: Option<String>,
+    /// Whether the status is known to be clear. Unlike a missing `last_sent`
+    /// this stays unset after a failed update, which may have gone through.
+    cleared: bool,
+    /// The update skipped last and why, so the status loop asking again and
+    /// again is only logged once
+    last_skipped: Option<(String, SkipReason)>,
+    latency_estimate: Duration,
+    breaker: CircuitBreaker,
+    state_file: Option<PathBuf>,
+    /// Whether the state file was read yet, which waits for the first update
+    state_loaded: bool,
+    /// Whether requests were sent since the state file was last written
+    state_changed: bool,
+    state_saved_at: Option<Instant>,
+    quiet: bool,
+    status_suffix: Option<String>,
+    /// Longest status `status_suffix` may make it
+    max_status_len: usize,
+    recorder: Recorder,
+}
+
+impl RateLimiter {
+    pub fn new(
+        client: Client,
+        config: &DiscordConfig,
+        token: &str,
+        account: Option<usize>,
+        recorder: Recorder,
+    ) -> Self {
+        Self {
+            name: match account {
+                Some(account) => format!("discord account {}", account),
+                None => "discord".to_string(),
+            },
+            api: DiscordApi::new(client, config, token),
+            clear_payload: config.clear_payload,
+            expire_status: config.expire_status,
+            expires_at: None,
+            last_expires_at: None,
+            requests: RequestWindow::new(config.rate_limit, config.rate_limit_window),
+            last_sent: None,
+            cleared: true,
+            last_skipped: None,
+            latency_estimate: Duration::ZERO,
+            breaker: CircuitBreaker::new(config.breaker_threshold, config.breaker_cooldown),
+            state_file: config.state_file.clone(),
+            state_loaded: false,
+            state_changed: false,
+            state_saved_at: None,
+            quiet: config.quiet,
+            status_suffix: config.status_suffix.clone(),
+            max_status_len: MAX_STATUS_LEN,
+            recorder,
+        }
+    }
+
+    /// Keeps statuses, suffix included, within `display.max_status_len`.
+    pub fn with_max_status_len(mut self, max_status_len: usize) -> Self {
+        self.max_status_len = max_status_len.clamp(1, MAX_STATUS_LEN);
+        self
+    }
+
+    /// Picks up where the last run left off, if there is a state file.
+    async fn load_state(&mut self) {
+        if self.state_loaded {
+            return;
+        }
+        self.state_loaded = true;
+        let Some(path) = self.state_file.clone() else {
+            return;
+        };
+
+        match read_state_file(&path).await {
+            Ok(mut states) => {
+                if let Some(state) = states.remove(&self.name) {
+                    self.restore(state);
+                }
+            }
+            // not worth failing over, the worst case is a rate limited update
+            Err(err) => tracing::warn!("Failed to read the state file: {}", err),
+        }
+    }
+
+    pub fn state(&self) -> RateLimiterState {
+        RateLimiterState {
+            saved_at: SystemTime::now(),
+            request_ages: self.requests.ages(),
+            latency_estimate: self.latency_estimate,
+        }
+    }
+
+    /// Takes over the requests from `state` that still count towards the
+    /// rate limit. A state that is too old to matter changes nothing but the
+    /// latency estimate.
+    pub fn restore(&mut self, state: RateLimiterState) {
+        // a clock that went backwards makes it look fresh, which errs on the safe side
+        let since_saved = state.saved_at.elapsed().unwrap_or_default();
+        self.requests
+            .restore(state.request_ages.into_iter().map(|age| age + since_saved));
+        self.latency_estimate = state.latency_estimate.min(MAX_LATENCY);
+    }
+
+    /// Writes the state file if requests were sent since it was last written,
+    /// at most every [`STATE_SAVE_INTERVAL`] unless `now` is set.
+    async fn save_state(&mut self, now: bool) {
+        let due = self
+            .state_saved_at
+            .is_none_or(|saved_at| saved_at.elapsed() >= STATE_SAVE_INTERVAL);
+        if !self.state_changed || !(now || due) {
+            return;
+        }
+        let Some(ref path) = self.state_file else {
+            return;
+        };
+
+        let result = async {
+            let _lock = STATE_FILE_LOCK.lock().await;
+            // other accounts share the file
+            let mut states = read_state_file(path).await.unwrap_or_default();
+            states.insert(self.name.clone(), self.state());
+            let contents = serde_json::to_string(&states)
+                .map_err(|err| DyricsError::Config(err.to_string()))?;
+            write_atomic(path, &contents).await
+        }
+        .await;
+        self.state_changed = false;
+        self.state_saved_at = Some(Instant::now());
+        if let Err(err) = result {
+            tracing::warn!("Failed to save the state file: {}", err);
+        }
+    }
+
+    pub fn breaker_state(&self) -> BreakerState {
+        self.breaker.state()
+    }
+
+    pub fn has_capacity(&mut self) -> bool {
+        self.requests.has_capacity()
+    }
+
+    fn update_latency(
+        &mut self,
+        sent_at: SystemTime,
+        round_trip: Duration,
+        server_time: Option<SystemTime>,
+    ) {
+        let latency = estimate_latency(sent_at, round_trip, server_time);
+        // smooth out single slow requests
+        self.latency_estimate = (self.latency_estimate * 3 + latency) / 4;
+    }
+
+    /// Counts a failed request towards pausing updates.
+    fn record_failure(&mut self, err: &DyricsError) {
+        // an invalid token won't fix itself, that is up to the caller
+        let outage = !matches!(err, DyricsError::DiscordAuth(_));
+        if outage && self.breaker.record_failure() {
+            tracing::warn!(
+                "Updating the {} status failed {} times in a row, pausing for {:?}",
+                self.name,
+                self.breaker.failures(),
+                self.breaker.cooldown
+            );
+        }
+    }
+
+    async fn send(&mut self, text: &str, emoji: &str) -> Result<(), DyricsError> {
+        let started = Instant::now();
+        let sent_at = SystemTime::now();
+        self.requests.record(started);
+
+        let text = with_suffix(text, self.status_suffix.as_deref(), self.max_status_len);
+        let result = self.api.send_status(&text, emoji, self.expires_at).await;
+        if let Ok(server_time) = result {
+            self.update_latency(sent_at, started.elapsed(), server_time);
+            self.recorder.record(|| Event::Status {
+                sink: self.name.clone(),
+                text,
+                emoji: emoji.to_string(),
+            });
+        }
+        // failed requests still count towards the limit
+        self.state_changed = true;
+        result?;
+
+        Ok(())
+    }
+
+    /// Clears the status, whether or not the rate limit allows it.
+    async fn clear(&mut self) -> Result<(), DyricsError> {
+        self.requests.record(Instant::now());
+        let result = self.api.clear_status(self.clear_payload).await;
+        self.state_changed = true;
+        match result {
+            Ok(()) => {
+                self.breaker.record_success();
+                self.last_sent = None;
+                self.cleared = true;
+                self.recorder.record(|| Event::Cleared {
+                    sink: self.name.clone(),
+                });
+            }
+            // counts towards pausing updates, so a failing clear backs off too
+            Err(ref err) => self.record_failure(err),
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl StatusSink for RateLimiter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set_expiry(&mut self, expires_at: Option<SystemTime>) {
+        self.expires_at = expires_at.filter(|_| self.expire_status);
+    }
+
+    fn latency(&self) -> Duration {
+        self.latency_estimate
+    }
+
+    async fn send_update(&mut self, text: &str, emoji: &str) -> Result<bool, DyricsError> {
+        self.load_state().await;
+        self.save_state(false).await;
+        // a repeated line still needs its own expiry
+        let showing =
+            self.last_sent.as_deref() == Some(text) && self.last_expires_at == self.expires_at;
+        let skipped = if showing {
+            Some(SkipReason::Duplicate)
+        } else if !self.breaker.allows_request() {
+            Some(SkipReason::Paused)
+        } else if !self.has_capacity() {
+            Some(SkipReason::RateLimited)
+        } else {
+            None
+        };
+        if let Some(reason) = skipped {
+            let logged = self
+                .last_skipped
+                .as_ref()
+                .is_some_and(|(skipped, skipped_for)| skipped == text && *skipped_for == reason);
+            if !logged {
+                tracing::debug!(sink = %self.name, ?reason, text, "status update skipped");
+                self.last_skipped = Some((text.to_string(), reason));
+            }
+            return Ok(false);
+        }
+
+        self.last_sent = Some(text.to_string());
+        self.last_expires_at = self.expires_at;
+        self.cleared = false;
+        match self.send(text, emoji).await {
+            Ok(()) => self.breaker.record_success(),
+            Err(err) => {
+                // it may not have gone through, try again once there is capacity
+                self.last_sent = None;
+                self.record_failure(&err);
+                return Err(err);
+            }
+        }
+        // the status loop keeps asking for what it just got, which says nothing
+        self.last_skipped = Some((text.to_string(), SkipReason::Duplicate));
+        if !self.quiet {
+            tracing::debug!(sink = %self.name, text, "status sent");
+        }
+
+        Ok(true)
+    }
+
+    async fn clear_status(&mut self) -> Result<(), DyricsError> {
+        self.load_state().await;
+        self.save_state(false).await;
+        // a clear on top of a clear would only use up a request
+        if self.cleared {
+            return Ok(());
+        }
+        // like any other update, the status loop asks again until it goes out
+        if !self.breaker.allows_request() || !self.has_capacity() {
+            return Ok(());
+        }
+        self.clear().await
+    }
+
+    async fn clear_on_shutdown(&mut self) -> Result<(), DyricsError> {
+        let result = if self.cleared {
+            Ok(())
+        } else {
+            // a pause doesn't matter for a single request on the way out, the
+            // rate limit does
+            let next_capacity = self.requests.next_capacity();
+            if next_capacity > Instant::now() + SHUTDOWN_CLEAR_TIMEOUT {
+                Err(DyricsError::ClearSkipped(self.name.clone()))
+            } else {
+                tokio::time::sleep_until(next_capacity.into()).await;
+                self.clear().await
+            }
+        };
+        self.save_state(true).await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{serve, serve_nothing};
+
+    fn api(base_url: String) -> DiscordApi {
+        api_with(base_url, &DiscordConfig::default())
+    }
+
+    fn api_with(base_url: String, config: &DiscordConfig) -> DiscordApi {
+        DiscordApi {
+            base_url: format!("{}/api", base_url),
+            ..DiscordApi::new(http_client(config).unwrap(), config, "token")
+        }
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn latency_is_narrowed_down_by_the_server_time() {
+        let sent_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+
+        // handled between 100ms in and the response arriving 400ms in
+        let server_time = sent_at + ms(100);
+        assert_eq!(
+            estimate_latency(sent_at, ms(400), Some(server_time)),
+            ms(250)
+        );
+        // half the round trip without a date header
+        assert_eq!(estimate_latency(sent_at, ms(400), None), ms(200));
+        // a server clock that is way off can't be used either
+        let server_time = sent_at + Duration::from_secs(5);
+        assert_eq!(
+            estimate_latency(sent_at, ms(400), Some(server_time)),
+            ms(200)
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_the_server_time_from_the_date_header() {
+        let (url, _) = serve("200 OK", "date: Wed, 21 Oct 2015 07:28:00 GMT\r\n", "{}").await;
+
+        let server_time = api(url).send_status("line", "", None).await.unwrap();
+
+        let expected = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z").unwrap();
+        assert_eq!(server_time, Some(SystemTime::from(expected)));
+    }
+
+    #[tokio::test]
+    async fn clears_with_the_configured_payload() {
+        let (url, requests) = serve("200 OK", "", r#"{"custom_status":null}"#).await;
+        let api = api(url);
+
+        api.clear_status(ClearPayload::Null).await.unwrap();
+        api.clear_status(ClearPayload::Empty).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("PATCH /api/v6/users/@me/settings "));
+        assert!(requests[0].ends_with(r#"{"custom_status":null}"#));
+        assert!(requests[1].ends_with(r#"{"custom_status":{"emoji_name":"","text":""}}"#));
+    }
+
+    #[tokio::test]
+    async fn a_hanging_update_times_out() {
+        let url = serve_nothing().await;
+        let config = DiscordConfig {
+            http_timeout: ms(100),
+            ..DiscordConfig::default()
+        };
+
+        let result = api_with(url, &config).send_status("line", "", None).await;
+
+        assert!(matches!(result, Err(DyricsError::Discord(err)) if err.is_timeout()));
+    }
+
+    #[tokio::test]
+    async fn clearing_on_shutdown_waits_for_the_rate_limit() {
+        let (url, requests) = serve("200 OK", "", "{}").await;
+        let config = DiscordConfig {
+            rate_limit: 1,
+            rate_limit_window: ms(200),
+            ..DiscordConfig::default()
+        };
+        let mut limiter = RateLimiter {
+            api: api(url),
+            quiet: true,
+            ..RateLimiter::new(Client::new(), &config, "token", None, Recorder::default())
+        };
+
+        limiter.send_update("a line", "").await.unwrap();
+        // held back until the status loop asks again
+        limiter.clear_status().await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        limiter.clear_on_shutdown().await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn clearing_on_shutdown_reports_giving_up() {
+        let (url, requests) = serve("200 OK", "", "{}").await;
+        let config = DiscordConfig {
+            rate_limit: 1,
+            rate_limit_window: Duration::from_secs(60),
+            ..DiscordConfig::default()
+        };
+        let mut limiter = RateLimiter {
+            api: api(url),
+            quiet: true,
+            ..RateLimiter::new(Client::new(), &config, "token", None, Recorder::default())
+        };
+
+        limiter.send_update("a line", "").await.unwrap();
+
+        assert!(matches!(
+            limiter.clear_on_shutdown().await,
+            Err(DyricsError::ClearSkipped(_))
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn accounts_share_the_state_file() {
+        let (url, _) = serve("200 OK", "", "{}").await;
+        let path = std::env::temp_dir().join(format!("dyrics-state-{}.json", std::process::id()));
+        let config = DiscordConfig {
+            state_file: Some(path.clone()),
+            ..DiscordConfig::default()
+        };
+        let limiter = |account| RateLimiter {
+            api: api(url.clone()),
+            quiet: true,
+            ..RateLimiter::new(
+                Client::new(),
+                &config,
+                "token",
+                Some(account),
+                Recorder::default(),
+            )
+        };
+
+        let mut first = limiter(1);
+        let mut second = limiter(2);
+        first.send_update("a line", "").await.unwrap();
+        second.send_update("a line", "").await.unwrap();
+        second.send_update("another line", "").await.unwrap();
+        first.clear_on_shutdown().await.unwrap();
+        second.clear_on_shutdown().await.unwrap();
+
+        let mut restarted = limiter(2);
+        restarted.load_state().await;
+        let states = read_state_file(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(states["discord account 1"].request_ages.len(), 2);
+        assert_eq!(states["discord account 2"].request_ages.len(), 3);
+        assert_eq!(restarted.requests.ages().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_emoji_out_of_the_text() {
+        let (url, requests) = serve("200 OK", "", "{}").await;
+        let config = DiscordConfig::default();
+        let mut limiter = RateLimiter {
+            api: api(url),
+            quiet: true,
+            ..RateLimiter::new(Client::new(), &config, "token", None, Recorder::default())
+        };
+
+        limiter.send_update("a line", "🎶").await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let body = requests[0].split_once("\r\n\r\n").unwrap().1;
+        let payload = serde_json::from_str::<Value>(body).unwrap();
+        assert_eq!(payload["custom_status"]["text"], "a line");
+        assert_eq!(payload["custom_status"]["emoji_name"], "🎶");
+    }
+
+    #[test]
+    fn breaker_opens_after_failures_in_a_row_and_half_opens_after_the_cooldown() {
+        let mut breaker = CircuitBreaker::new(2, ms(50));
+
+        assert!(!breaker.record_failure());
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.record_failure());
+        assert!(matches!(breaker.state(), BreakerState::Open { .. }));
+        assert!(!breaker.allows_request());
+
+        std::thread::sleep(ms(60));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(breaker.allows_request());
+        // the attempt while half open failed too
+        assert!(breaker.record_failure());
+        assert!(!breaker.allows_request());
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert_eq!(breaker.failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn failing_updates_pause_sending() {
+        let (url, requests) = serve("500 Internal Server Error", "", "{}").await;
+        let config = DiscordConfig {
+            breaker_threshold: 2,
+            ..DiscordConfig::default()
+        };
+        let mut limiter = RateLimiter {
+            api: api(url),
+            quiet: true,
+            ..RateLimiter::new(Client::new(), &config, "token", None, Recorder::default())
+        };
+
+        assert!(limiter.send_update("one", "").await.is_err());
+        assert!(limiter.send_update("two", "").await.is_err());
+        assert!(!limiter.send_update("three", "").await.unwrap());
+        assert!(matches!(limiter.breaker_state(), BreakerState::Open { .. }));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn unicode_and_custom_emoji_use_their_own_field() {
+        assert_eq!(
+            status_payload("a line", "🎶", None),
+            json!({ "custom_status": { "text": "a line", "emoji_name": "🎶" } })
+        );
+        assert_eq!(
+            status_payload("a line", "notes:123456789", None),
+            json!({
+                "custom_status": {
+                    "text": "a line",
+                    "emoji_id": "123456789",
+                    "emoji_name": null,
+                    "animated": false
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parses_animated_static_and_unicode_emoji() {
+        let custom = |name, id, animated| Emoji::Custom { name, id, animated };
+        assert_eq!(Emoji::parse("🎶"), Emoji::Unicode("🎶"));
+        assert_eq!(Emoji::parse("notes:123"), custom("notes", "123", false));
+        assert_eq!(Emoji::parse("<:notes:123>"), custom("notes", "123", false));
+        assert_eq!(Emoji::parse("a:notes:123"), custom("notes", "123", true));
+        assert_eq!(Emoji::parse("<a:notes:123>"), custom("notes", "123", true));
+        // a static emoji that happens to be called "a"
+        assert_eq!(Emoji::parse("a:123"), custom("a", "123", false));
+        assert_eq!(Emoji::parse("notes:abc"), Emoji::Unicode("notes:abc"));
+
+        assert_eq!(
+            status_payload("a line", "<a:notes:123>", None),
+            json!({
+                "custom_status": {
+                    "text": "a line",
+                    "emoji_id": "123",
+                    "emoji_name": null,
+                    "animated": true
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn suffix_is_dropped_when_it_doesnt_fit() {
+        assert_eq!(
+            with_suffix("a line", Some(" via Spotify"), 128),
+            "a line via Spotify"
+        );
+        assert_eq!(with_suffix("a line", Some(" via Spotify"), 17), "a line");
+        assert_eq!(
+            with_suffix("a line", Some(" via Spotify"), 18),
+            "a line via Spotify"
+        );
+        // an emoji-only status stays that way
+        assert_eq!(with_suffix("", Some(" via Spotify"), 128), "");
+        assert_eq!(with_suffix("a line", None, 128), "a line");
+    }
+
+    #[tokio::test]
+    async fn suffix_is_kept_within_max_status_len() {
+        let (url, requests) = serve("200 OK", "", "{}").await;
+        let config = DiscordConfig {
+            status_suffix: Some(" via Spotify".to_string()),
+            ..DiscordConfig::default()
+        };
+        let mut limiter = RateLimiter {
+            api: api(url),
+            quiet: true,
+            ..RateLimiter::new(Client::new(), &config, "token", None, Recorder::default())
+        }
+        .with_max_status_len(20);
+
+        limiter.send_update("short", "").await.unwrap();
+        limiter.send_update("a longer line", "").await.unwrap();
+
+        let texts = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let body = request.split_once("\r\n\r\n").unwrap().1;
+                let payload = serde_json::from_str::<Value>(body).unwrap();
+                payload["custom_status"]["text"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["short via Spotify", "a longer line"]);
+    }
+
+    #[tokio::test]
+    async fn the_status_read_back_is_restored_as_it_was() {
+        let (url, requests) = serve(
+            "200 OK",
+            "",
+            r#"{"custom_status": {"text": "before", "emoji_name": "🎵"}, "theme": "dark"}"#,
+        )
+        .await;
+        let api = api(url);
+
+        let previous = api.custom_status().await.unwrap();
+        assert_eq!(previous, json!({ "text": "before", "emoji_name": "🎵" }));
+        api.restore_status(&previous).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let (_, body) = requests[1].split_once("\r\n\r\n").unwrap();
+        assert!(requests[1].starts_with("PATCH /api/v6/users/@me/settings "));
+        assert_eq!(
+            serde_json::from_str::<Value>(body).unwrap(),
+            json!({ "custom_status": { "text": "before", "emoji_name": "🎵" } })
+        );
+    }
+
+    #[tokio::test]
+    async fn no_status_is_restored_as_null() {
+        let (url, requests) = serve("200 OK", "", r#"{"theme": "dark"}"#).await;
+        let api = api(url);
+
+        let previous = api.custom_status().await.unwrap();
+        assert_eq!(previous, Value::Null);
+        api.restore_status(&previous).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let (_, body) = requests[1].split_once("\r\n\r\n").unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(body).unwrap(),
+            json!({ "custom_status": null })
+        );
+    }
+
+    #[tokio::test]
+    async fn clearing_twice_sends_one_request() {
+        let (url, requests) = serve("200 OK", "", "{}").await;
+        let config = DiscordConfig::default();
+        let mut limiter = RateLimiter {
+            api: api(url),
+            quiet: true,
+            ..RateLimiter::new(Client::new(), &config, "token", None, Recorder::default())
+        };
+
+        // nothing was sent yet, so there is nothing to clear
+        limiter.clear_status().await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 0);
+
+        limiter.send_update("a line", "").await.unwrap();
+        limiter.clear_status().await.unwrap();
+        limiter.clear_status().await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn tokens_get_the_prefix_of_their_type() {
+        // a user token pasted with a prefix it shouldn't have
+        assert_eq!(authorization("Bearer abc", TokenType::Auto), "abc");
+        assert_eq!(authorization(" bearer  abc ", TokenType::Auto), "abc");
+        assert_eq!(authorization("abc", TokenType::Auto), "abc");
+        assert_eq!(authorization("Bot abc", TokenType::Auto), "Bot abc");
+
+        assert_eq!(authorization("Bot abc", TokenType::User), "abc");
+        assert_eq!(authorization("Bearer abc", TokenType::User), "abc");
+
+        assert_eq!(authorization("abc", TokenType::Bot), "Bot abc");
+        assert_eq!(authorization("bot abc", TokenType::Bot), "Bot abc");
+        assert_eq!(authorization("Bearer abc", TokenType::Bot), "Bot abc");
+    }
+
+    #[test]
+    fn emoji_only_statuses_leave_out_the_text() {
+        assert_eq!(
+            status_payload("", "🎶", None),
+            json!({ "custom_status": { "emoji_name": "🎶" } })
+        );
+        assert_eq!(
+            status_payload("", "notes:123", None),
+            json!({
+                "custom_status": {
+                    "emoji_id": "123",
+                    "emoji_name": null,
+                    "animated": false
+                }
+            })
+        );
+    }
+}
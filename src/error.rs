@@ -0,0 +1,34 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DyricsError {
+    #[error("discord request failed: {0}")]
+    Discord(#[source] reqwest::Error),
+    #[error("your Discord token is invalid (discord responded with {0})")]
+    DiscordAuth(StatusCode),
+    #[error("the webhook is gone or invalid (discord responded with {0}), check webhook.url in your config")]
+    WebhookRejected(StatusCode),
+    #[error("logging in with spotify failed after {attempts} attempt(s): {source}")]
+    Auth {
+        attempts: u32,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("gave up clearing the {0} status, the rate limit is used up")]
+    ClearSkipped(String),
+    #[error("invalid config: {0}")]
+    Config(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "notifications")]
+    #[error("failed to show notification: {0}")]
+    Notification(String),
+}
+
+impl DyricsError {
+    /// Whether the request just took too long and is worth trying again.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, DyricsError::Discord(err) if err.is_timeout())
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    config::FileConfig,
+    error::DyricsError,
+    sink::{prepend_emoji, StatusSink},
+};
+
+/// Writes `contents` to a sibling temp file and renames it over `path`, so
+/// readers never see a half-written file.
+pub(crate) async fn write_atomic(path: &Path, contents: &str) -> Result<(), DyricsError> {
+    let mut temp_name = OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    tokio::fs::write(&temp_path, contents).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+
+    Ok(())
+}
+
+/// Mirrors the status into plain text files, e.g. for OBS text sources or
+/// status bars.
+pub struct FileSink {
+    path: PathBuf,
+    emoji_path: Option<PathBuf>,
+    prepend_emoji: bool,
+    last_sent: Option<String>,
+}
+
+impl FileSink {
+    pub fn new(config: &FileConfig) -> Self {
+        Self {
+            path: config.path.clone(),
+            emoji_path: config.emoji_path.clone(),
+            prepend_emoji: config.prepend_emoji,
+            last_sent: None,
+        }
+    }
+
+    async fn write(&self, text: &str, emoji: &str) -> Result<(), DyricsError> {
+        if self.prepend_emoji {
+            write_atomic(&self.path, &prepend_emoji(text, emoji)).await?;
+        } else {
+            write_atomic(&self.path, text).await?;
+        }
+        if let Some(ref emoji_path) = self.emoji_path {
+            write_atomic(emoji_path, emoji).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StatusSink for FileSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn send_update(&mut self, text: &str, emoji: &str) -> Result<bool, DyricsError> {
+        if self.last_sent.as_deref() == Some(text) {
+            return Ok(false);
+        }
+
+        self.write(text, emoji).await?;
+        self.last_sent = Some(text.to_string());
+
+        Ok(true)
+    }
+
+    async fn clear_status(&mut self) -> Result<(), DyricsError> {
+        if self.last_sent.take().is_none() {
+            return Ok(());
+        }
+
+        self.write("", "").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn prepends_the_emoji_when_asked_to() {
+        let dir = std::env::temp_dir().join(format!("dyrics-file-sink-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let config = FileConfig {
+            path: dir.join("status.txt"),
+            emoji_path: Some(dir.join("emoji.txt")),
+            prepend_emoji: true,
+        };
+        let mut sink = FileSink::new(&config);
+
+        sink.send_update("a line", "🎶").await.unwrap();
+
+        let status = tokio::fs::read_to_string(&config.path).await.unwrap();
+        let emoji = tokio::fs::read_to_string(dir.join("emoji.txt"))
+            .await
+            .unwrap();
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+        assert_eq!(status, "🎶 a line");
+        assert_eq!(emoji, "🎶");
+    }
+}
@@ -0,0 +1,29 @@
+pub mod activity;
+pub mod bio;
+pub mod cert;
+pub mod config;
+pub mod discord;
+pub mod error;
+pub mod file;
+pub mod lyrics;
+#[cfg(feature = "mpris")]
+pub mod mpris;
+#[cfg(feature = "notifications")]
+pub mod notification;
+pub mod playback;
+pub mod profanity;
+pub mod provider;
+pub mod recorder;
+pub mod replay;
+pub mod schedule;
+pub mod shutdown;
+pub mod sink;
+pub mod source;
+pub mod spotify;
+pub mod status;
+pub mod template;
+#[cfg(test)]
+mod test_util;
+pub mod text;
+pub mod translation;
+pub mod webhook;
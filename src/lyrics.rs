@@ -0,0 +1,750 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use serde_with::DurationSecondsWithFrac;
+
+use crate::{config::MatchMode, text::is_unspaced_script};
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Lyrics {
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub start_time: Duration,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub end_time: Duration,
+    #[serde(flatten)]
+    pub content: LyricsContent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", tag = "Type", content = "Content")]
+pub enum LyricsContent {
+    Syllable(Vec<SyllableLyricsLine>),
+    Line(Vec<LineLyricsLine>),
+    //Static(), TODO: Fix, we need not "content" but "lines" for this
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SyllableLyricsLine {
+    pub r#type: String,
+    pub opposite_aligned: bool,
+    pub lead: SyllableLyricsLead,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SyllableLyricsLead {
+    pub syllables: Vec<SyllableLyricsSyllable>,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub start_time: Duration,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub end_time: Duration,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SyllableLyricsSyllable {
+    pub text: String,
+    /// The syllable in latin script, for lyrics that aren't
+    #[serde(default)]
+    pub romanized_text: Option<String>,
+    pub is_part_of_word: bool,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub start_time: Duration,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub end_time: Duration,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LineLyricsLine {
+    pub r#type: String,
+    pub opposite_aligned: bool,
+    pub text: String,
+    /// The line in latin script, for lyrics that aren't
+    #[serde(default)]
+    pub romanized_text: Option<String>,
+    /// Filled in by the [`Translator`](crate::translation::Translator), if
+    /// there is one
+    #[serde(skip)]
+    pub translated_text: Option<String>,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub start_time: Duration,
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub end_time: Duration,
+}
+
+fn syllable_contains_duration(item: &SyllableLyricsLead, duration: Duration) -> bool {
+    item.start_time <= duration && duration <= item.end_time
+}
+
+fn syllable_distance_to(item: &SyllableLyricsLead, duration: Duration) -> Duration {
+    if duration < item.start_time {
+        item.start_time.saturating_sub(duration)
+    } else if duration > item.end_time {
+        duration.saturating_sub(item.end_time)
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+/// The line being sung at `timestamp`, along with its index. Of overlapping
+/// lines (e.g. background vocals), the one that started last wins.
+fn syllable_line_at(
+    lines: &[SyllableLyricsLine],
+    timestamp: Duration,
+) -> Option<(usize, &SyllableLyricsLine)> {
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| syllable_contains_duration(&line.lead, timestamp))
+        .max_by_key(|(_, line)| line.lead.start_time)
+}
+
+/// The line being sung at `target`, or with [`MatchMode::Nearest`] the
+/// closest one when `target` falls between lines.
+pub fn find_nearest_syllable_line(
+    items: &[SyllableLyricsLine],
+    target: Duration,
+    mode: MatchMode,
+) -> Option<&SyllableLyricsLine> {
+    // of overlapping lines (e.g. background vocals), the one that started last wins
+    items
+        .iter()
+        .filter(|item| syllable_contains_duration(&item.lead, target))
+        .max_by_key(|item| item.lead.start_time)
+        .or_else(|| match mode {
+            MatchMode::Nearest => items
+                .iter()
+                .min_by_key(|item| syllable_distance_to(&item.lead, target)),
+            MatchMode::Strict => None,
+        })
+}
+
+fn line_contains_duration(line: &LineLyricsLine, duration: Duration) -> bool {
+    line.start_time <= duration && duration <= line.end_time
+}
+
+fn line_distance_to(line: &LineLyricsLine, duration: Duration) -> Duration {
+    if duration < line.start_time {
+        line.start_time.saturating_sub(duration)
+    } else if duration > line.end_time {
+        duration.saturating_sub(line.end_time)
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+/// The line being sung at `target`, or with [`MatchMode::Nearest`] the
+/// closest one when `target` falls between lines.
+pub fn find_nearest_line(
+    lines: &[LineLyricsLine],
+    target: Duration,
+    mode: MatchMode,
+) -> Option<&LineLyricsLine> {
+    // of overlapping lines (e.g. background vocals), the one that started last wins
+    lines
+        .iter()
+        .filter(|line| line_contains_duration(line, target))
+        .max_by_key(|line| line.start_time)
+        .or_else(|| match mode {
+            MatchMode::Nearest => lines
+                .iter()
+                .min_by_key(|line| line_distance_to(line, target)),
+            MatchMode::Strict => None,
+        })
+}
+
+/// A single line of lyrics, regardless of how precisely the provider timed it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedLine {
+    pub text: String,
+    pub start_time: Duration,
+    pub end_time: Duration,
+    /// When each whitespace separated word of `text` starts, empty if the
+    /// provider only timed the whole line
+    pub word_starts: Vec<Duration>,
+    /// The part of the song the line belongs to, e.g. a verse or the chorus
+    pub section: Option<String>,
+    /// The type the provider gave the line, which `section` is taken from
+    pub line_type: String,
+    /// Whether the line is shown on the other side, usually because someone
+    /// else sings it
+    pub opposite_aligned: bool,
+    /// The line in latin script, if the provider romanized it
+    pub romanized: Option<String>,
+    /// The line in the language lyrics are translated into, if it isn't in
+    /// that language already
+    pub translated: Option<String>,
+}
+
+impl TimedLine {
+    /// When each word of the line starts, spreading the words evenly over the
+    /// line if the provider didn't time them.
+    pub fn word_timings(&self) -> Vec<Duration> {
+        let word_count = self.text.split_whitespace().count();
+        if self.word_starts.len() == word_count {
+            return self.word_starts.clone();
+        }
+
+        let total_chars = self.text.chars().count().max(1) as f64;
+        let line_duration = self.end_time.saturating_sub(self.start_time);
+        let mut timings = Vec::with_capacity(word_count);
+        let mut at_boundary = true;
+        for (offset, c) in self.text.chars().enumerate() {
+            if c.is_whitespace() {
+                at_boundary = true;
+            } else if at_boundary {
+                at_boundary = false;
+                timings.push(self.start_time + line_duration.mul_f64(offset as f64 / total_chars));
+            }
+        }
+        timings
+    }
+}
+
+/// Puts a line's times in order. Corrupt provider data occasionally has a
+/// line end before it starts, most likely with the two mixed up.
+fn ordered_times(start_time: Duration, end_time: Duration) -> (Duration, Duration) {
+    if end_time < start_time {
+        (end_time, start_time)
+    } else {
+        (start_time, end_time)
+    }
+}
+
+/// Joins syllables into a line, along with the start time of every word in it.
+/// Syllables of scripts written without spaces are never spaced apart, even
+/// when the provider marks them as separate words.
+fn join_syllables(syllables: &[SyllableLyricsSyllable]) -> (String, Vec<Duration>) {
+    join_syllable_texts(syllables, |syllable| &syllable.text, None)
+}
+
+/// The romanized line, if any of its syllables have been romanized.
+fn join_romanized_syllables(syllables: &[SyllableLyricsSyllable]) -> Option<String> {
+    syllables
+        .iter()
+        .any(|syllable| syllable.romanized_text.is_some())
+        .then(|| {
+            join_syllable_texts(
+                syllables,
+                |syllable| syllable.romanized_text.as_deref().unwrap_or(&syllable.text),
+                None,
+            )
+            .0
+        })
+}
+
+/// `cursor` puts markers around the syllable at its index, leaving the
+/// spacing as if they weren't there.
+fn join_syllable_texts<'a>(
+    syllables: &'a [SyllableLyricsSyllable],
+    text_of: impl Fn(&'a SyllableLyricsSyllable) -> &'a str,
+    cursor: Option<(usize, &str, &str)>,
+) -> (String, Vec<Duration>) {
+    let mut text = String::new();
+    let mut word_starts = Vec::new();
+    let mut at_boundary = true;
+    for (index, syllable) in syllables.iter().enumerate() {
+        // some providers pad syllables with the space that follows or
+        // precedes them, which would double up with the one added here
+        let padded = text_of(syllable);
+        let syllable_text = padded.trim();
+        if padded.starts_with(char::is_whitespace) && !text.is_empty() && !text.ends_with(' ') {
+            text.push(' ');
+            at_boundary = true;
+        }
+
+        for c in syllable_text.chars() {
+            if c.is_whitespace() {
+                at_boundary = true;
+            } else if at_boundary {
+                at_boundary = false;
+                word_starts.push(syllable.start_time);
+            }
+        }
+
+        match cursor {
+            Some((cursor, open, close)) if cursor == index => {
+                text.push_str(open);
+                text.push_str(syllable_text);
+                text.push_str(close);
+            }
+            _ => text.push_str(syllable_text),
+        }
+        let unspaced = syllable_text.chars().last().is_some_and(is_unspaced_script)
+            && syllables
+                .get(index + 1)
+                .and_then(|next| text_of(next).trim_start().chars().next())
+                .is_some_and(is_unspaced_script);
+        let word_ends = !syllable.is_part_of_word || padded.ends_with(char::is_whitespace);
+        if word_ends && !unspaced && !text.is_empty() && !text.ends_with(' ') {
+            text.push(' ');
+            at_boundary = true;
+        }
+    }
+    (text.trim_end().to_string(), word_starts)
+}
+
+/// The section a line is in, taken from its type.
+fn section(line_type: &str) -> Option<String> {
+    let line_type = line_type.trim();
+    (!line_type.is_empty()).then(|| line_type.to_string())
+}
+
+/// Parses an LRC timestamp like `01:23.45`.
+fn parse_lrc_time(time: &str) -> Option<Duration> {
+    let (minutes, seconds) = time.split_once(':')?;
+    let minutes = minutes.trim().parse::<u64>().ok()?;
+    let seconds = seconds.trim().parse::<f64>().ok()?;
+    (seconds.is_finite() && seconds >= 0.0)
+        .then(|| Duration::from_secs(minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+impl Lyrics {
+    /// Reads line synced lyrics in the LRC format, e.g. `[00:12.34] a line`.
+    /// Each line lasts until the next one starts, the last one until the end
+    /// of the track. Returns `None` if there are no timed lines.
+    pub fn from_lrc(lrc: &str, duration: Duration) -> Option<Lyrics> {
+        let mut timed = Vec::new();
+        for line in lrc.lines() {
+            // a line can have several timestamps if it repeats
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+            while let Some((tag, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+                // tags like [ar:Artist] aren't timestamps
+                let Some(time) = parse_lrc_time(tag) else {
+                    break;
+                };
+                times.push(time);
+                rest = after;
+            }
+            timed.extend(
+                times
+                    .into_iter()
+                    .map(|time| (time, rest.trim().to_string())),
+            );
+        }
+        timed.sort_by_key(|&(time, _)| time);
+
+        // empty lines only mark where the line before them ends
+        let lines = timed
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, text))| !text.is_empty())
+            .map(|(index, (start_time, text))| LineLyricsLine {
+                r#type: "Vocal".to_string(),
+                opposite_aligned: false,
+                text: text.clone(),
+                romanized_text: None,
+                translated_text: None,
+                start_time: *start_time,
+                end_time: timed
+                    .get(index + 1)
+                    .map(|&(time, _)| time)
+                    .unwrap_or(duration)
+                    .max(*start_time),
+            })
+            .collect::<Vec<_>>();
+
+        Some(Lyrics {
+            start_time: lines.first()?.start_time,
+            end_time: lines.last()?.end_time,
+            content: LyricsContent::Line(lines),
+        })
+    }
+
+    /// Moves every line `offset` seconds later, or earlier if it is negative.
+    /// Nothing moves before the start of the song.
+    pub fn shift(&mut self, offset: f64) {
+        if offset == 0.0 || !offset.is_finite() {
+            return;
+        }
+        let shift = |time: &mut Duration| {
+            *time = Duration::from_secs_f64((time.as_secs_f64() + offset).max(0.0));
+        };
+
+        shift(&mut self.start_time);
+        shift(&mut self.end_time);
+        match self.content {
+            LyricsContent::Syllable(ref mut lines) => {
+                for line in lines {
+                    shift(&mut line.lead.start_time);
+                    shift(&mut line.lead.end_time);
+                    for syllable in &mut line.lead.syllables {
+                        shift(&mut syllable.start_time);
+                        shift(&mut syllable.end_time);
+                    }
+                }
+            }
+            LyricsContent::Line(ref mut lines) => {
+                for line in lines {
+                    shift(&mut line.start_time);
+                    shift(&mut line.end_time);
+                }
+            }
+        }
+    }
+
+    /// Turns lyrics synced by syllable into lyrics synced by line.
+    pub fn into_lines(self) -> Lyrics {
+        let LyricsContent::Syllable(lines) = self.content else {
+            return self;
+        };
+
+        let lines = lines
+            .into_iter()
+            .map(|line| LineLyricsLine {
+                text: join_syllables(&line.lead.syllables).0,
+                romanized_text: join_romanized_syllables(&line.lead.syllables),
+                translated_text: None,
+                start_time: line.lead.start_time,
+                end_time: line.lead.end_time,
+                r#type: line.r#type,
+                opposite_aligned: line.opposite_aligned,
+            })
+            .collect();
+        Lyrics {
+            content: LyricsContent::Line(lines),
+            ..self
+        }
+    }
+
+    /// Flattens the lyrics into lines sorted by their start time.
+    pub fn get_timed_lines(&self) -> Vec<TimedLine> {
+        let mut lines = match self.content {
+            LyricsContent::Syllable(ref lines) => lines
+                .iter()
+                .map(|line| {
+                    let (text, word_starts) = join_syllables(&line.lead.syllables);
+                    let (start_time, end_time) =
+                        ordered_times(line.lead.start_time, line.lead.end_time);
+                    TimedLine {
+                        text,
+                        start_time,
+                        end_time,
+                        word_starts,
+                        section: section(&line.r#type),
+                        line_type: line.r#type.clone(),
+                        opposite_aligned: line.opposite_aligned,
+                        romanized: join_romanized_syllables(&line.lead.syllables),
+                        translated: None,
+                    }
+                })
+                .collect::<Vec<_>>(),
+            LyricsContent::Line(ref lines) => lines
+                .iter()
+                .map(|line| {
+                    let (start_time, end_time) = ordered_times(line.start_time, line.end_time);
+                    TimedLine {
+                        text: line.text.clone(),
+                        start_time,
+                        end_time,
+                        word_starts: Vec::new(),
+                        section: section(&line.r#type),
+                        line_type: line.r#type.clone(),
+                        opposite_aligned: line.opposite_aligned,
+                        romanized: line.romanized_text.clone(),
+                        translated: line.translated_text.clone(),
+                    }
+                })
+                .collect(),
+        };
+        lines.sort_by_key(|line| line.start_time);
+        lines
+    }
+
+    /// The syllable being sung at `timestamp`, as indices of the line and the
+    /// syllable within its lead, e.g. to highlight it in a karaoke overlay.
+    /// Between two syllables of a line it is the one that started last.
+    /// Returns `None` between lines, before the first syllable of a line and
+    /// for lyrics that aren't synced by syllable.
+    pub fn current_syllable(&self, timestamp: Duration) -> Option<(usize, usize)> {
+        let LyricsContent::Syllable(ref lines) = self.content else {
+            return None;
+        };
+
+        let (line_index, line) = syllable_line_at(lines, timestamp)?;
+        let syllable_index = line
+            .lead
+            .syllables
+            .iter()
+            .rposition(|syllable| syllable.start_time <= timestamp)?;
+
+        Some((line_index, syllable_index))
+    }
+
+    /// The line being sung at `timestamp` with `open` and `close` around the
+    /// syllable being sung, e.g. `"["` and `"]"` for a karaoke display in a
+    /// terminal. Picks the line and syllable like [`Lyrics::current_syllable`],
+    /// except that the line is returned without markers before its first
+    /// syllable. Returns `None` between lines and for lyrics that aren't
+    /// synced by syllable.
+    pub fn render_line_with_cursor(
+        &self,
+        timestamp: Duration,
+        open: &str,
+        close: &str,
+    ) -> Option<String> {
+        let LyricsContent::Syllable(ref lines) = self.content else {
+            return None;
+        };
+
+        let (_, line) = syllable_line_at(lines, timestamp)?;
+        let cursor = line
+            .lead
+            .syllables
+            .iter()
+            .rposition(|syllable| syllable.start_time <= timestamp)
+            .map(|index| (index, open, close));
+
+        Some(join_syllable_texts(&line.lead.syllables, |syllable| &syllable.text, cursor).0)
+    }
+
+    /// The text of the line at `timestamp`, see [`MatchMode`] for what
+    /// happens between lines.
+    pub fn get_text_at(&self, timestamp: Duration, mode: MatchMode) -> Option<String> {
+        match self.content {
+            LyricsContent::Syllable(ref lines) => {
+                find_nearest_syllable_line(lines, timestamp, mode)
+                    .map(|line| join_syllables(&line.lead.syllables).0)
+            }
+            LyricsContent::Line(ref lines) => {
+                find_nearest_line(lines, timestamp, mode).map(|line| line.text.clone())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Line synced lyrics of `(text, start, end)` in milliseconds.
+    fn line_lyrics(lines: &[(&str, u64, u64)]) -> Lyrics {
+        let lines = lines
+            .iter()
+            .map(|&(text, start, end)| LineLyricsLine {
+                r#type: "Vocal".to_string(),
+                opposite_aligned: false,
+                text: text.to_string(),
+                romanized_text: None,
+                translated_text: None,
+                start_time: ms(start),
+                end_time: ms(end),
+            })
+            .collect::<Vec<_>>();
+        Lyrics {
+            start_time: lines
+                .first()
+                .map(|line| line.start_time)
+                .unwrap_or_default(),
+            end_time: lines.last().map(|line| line.end_time).unwrap_or_default(),
+            content: LyricsContent::Line(lines),
+        }
+    }
+
+    /// Syllable synced lyrics of one line per list of `(text, is_part_of_word)`,
+    /// each syllable lasting 100 milliseconds.
+    fn syllable_lyrics(lines: &[&[(&str, bool)]]) -> Lyrics {
+        let mut at = 0;
+        let lines = lines
+            .iter()
+            .map(|syllables| {
+                let start_time = ms(at);
+                let syllables = syllables
+                    .iter()
+                    .map(|&(text, is_part_of_word)| {
+                        at += 100;
+                        SyllableLyricsSyllable {
+                            text: text.to_string(),
+                            romanized_text: None,
+                            is_part_of_word,
+                            start_time: ms(at - 100),
+                            end_time: ms(at),
+                        }
+                    })
+                    .collect();
+                SyllableLyricsLine {
+                    r#type: "Vocal".to_string(),
+                    opposite_aligned: false,
+                    lead: SyllableLyricsLead {
+                        syllables,
+                        start_time,
+                        end_time: ms(at),
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+        Lyrics {
+            start_time: Duration::ZERO,
+            end_time: ms(at),
+            content: LyricsContent::Syllable(lines),
+        }
+    }
+
+    fn texts(lyrics: &Lyrics) -> Vec<String> {
+        lyrics
+            .get_timed_lines()
+            .into_iter()
+            .map(|line| line.text)
+            .collect()
+    }
+
+    #[test]
+    fn overlapping_lines_pick_the_latest_start() {
+        let lyrics = line_lyrics(&[("lead", 0, 4000), ("backing", 2000, 3000)]);
+
+        let text_at = |at| lyrics.get_text_at(ms(at), MatchMode::Strict);
+        assert_eq!(text_at(1000).as_deref(), Some("lead"));
+        assert_eq!(text_at(2500).as_deref(), Some("backing"));
+        assert_eq!(text_at(3500).as_deref(), Some("lead"));
+    }
+
+    #[test]
+    fn unspaced_scripts_join_without_spaces() {
+        let lyrics = syllable_lyrics(&[
+            &[("東", false), ("京", false), ("is", false), ("big", false)],
+            &[("Hello", false), ("世", false), ("界", false), ("!", false)],
+            &[("สวัส", false), ("ดี", false), ("ครับ", false)],
+        ]);
+
+        assert_eq!(texts(&lyrics), ["東京 is big", "Hello 世界 !", "สวัสดีครับ"]);
+    }
+
+    #[test]
+    fn current_syllable_is_the_last_one_started() {
+        let mut lyrics = syllable_lyrics(&[
+            &[("a", false), ("line", false)],
+            &[("an", true), ("oth", true), ("er", false)],
+        ]);
+        if let LyricsContent::Syllable(ref mut lines) = lyrics.content {
+            // a pause between the syllables of the first line
+            lines[0].lead.syllables[1].start_time = ms(150);
+            // and before the first syllable of the second
+            lines[1].lead.syllables[0].start_time = ms(250);
+        }
+
+        assert_eq!(lyrics.current_syllable(ms(50)), Some((0, 0)));
+        assert_eq!(lyrics.current_syllable(ms(120)), Some((0, 0)));
+        assert_eq!(lyrics.current_syllable(ms(150)), Some((0, 1)));
+        assert_eq!(lyrics.current_syllable(ms(220)), None);
+        assert_eq!(lyrics.current_syllable(ms(350)), Some((1, 1)));
+        assert_eq!(lyrics.current_syllable(ms(600)), None);
+        assert_eq!(
+            line_lyrics(&[("a line", 0, 100)]).current_syllable(ms(50)),
+            None
+        );
+    }
+
+    #[test]
+    fn inverted_time_ranges_are_put_in_order() {
+        let lyrics = line_lyrics(&[("first", 0, 1000), ("inverted", 3000, 2000)]);
+
+        let lines = lyrics.get_timed_lines();
+        assert_eq!(
+            (lines[1].start_time, lines[1].end_time),
+            (ms(2000), ms(3000))
+        );
+        assert!(lines[1].word_timings().iter().all(|&at| at >= ms(2000)));
+        for at in [0, 1500, 2500, 5000] {
+            lyrics.get_text_at(ms(at), MatchMode::Nearest);
+        }
+
+        let mut lyrics = syllable_lyrics(&[&[("a", false), ("line", false)]]);
+        if let LyricsContent::Syllable(ref mut lines) = lyrics.content {
+            lines[0].lead.start_time = ms(200);
+            lines[0].lead.end_time = Duration::ZERO;
+        }
+        let lines = lyrics.get_timed_lines();
+        assert_eq!(
+            (lines[0].start_time, lines[0].end_time),
+            (Duration::ZERO, ms(200))
+        );
+        for at in [0, 100, 500] {
+            lyrics.get_text_at(ms(at), MatchMode::Nearest);
+            lyrics.current_syllable(ms(at));
+        }
+    }
+
+    #[test]
+    fn padded_syllables_dont_double_up_spaces() {
+        let lyrics = syllable_lyrics(&[
+            &[("Hel", true), ("lo ", false), (" world", false)],
+            &[
+                (" lead", true),
+                ("ing", false),
+                (" space ", false),
+                ("two  words", false),
+            ],
+        ]);
+
+        assert_eq!(texts(&lyrics), ["Hello world", "leading space two  words"]);
+    }
+
+    #[test]
+    fn cursor_marks_the_syllable_being_sung() {
+        let mut lyrics = syllable_lyrics(&[
+            &[("a", false), ("line", false)],
+            &[("an", true), ("oth", true), ("er", false)],
+            &[("oh", false)],
+        ]);
+        if let LyricsContent::Syllable(ref mut lines) = lyrics.content {
+            // a pause before the first syllable of the second line
+            lines[1].lead.syllables[0].start_time = ms(250);
+        }
+        let render = |at| lyrics.render_line_with_cursor(ms(at), "[", "]");
+
+        assert_eq!(render(50).as_deref(), Some("[a] line"));
+        assert_eq!(render(150).as_deref(), Some("a [line]"));
+        assert_eq!(render(220).as_deref(), Some("another"));
+        assert_eq!(render(350).as_deref(), Some("an[oth]er"));
+        assert_eq!(render(450).as_deref(), Some("anoth[er]"));
+        assert_eq!(render(550).as_deref(), Some("[oh]"));
+        assert_eq!(render(700), None);
+        assert_eq!(
+            line_lyrics(&[("a line", 0, 100)]).render_line_with_cursor(ms(50), "[", "]"),
+            None
+        );
+    }
+
+    #[test]
+    fn reads_lrc_lines_until_the_next_one() {
+        let lrc = "[ar:Artist]\n\
+                   [00:01.00] first\n\
+                   [00:03.50][00:10.00] chorus\n\
+                   [00:05.00]\n\
+                   [00:06.25] second";
+        let lyrics = Lyrics::from_lrc(lrc, ms(12_000)).unwrap();
+
+        let lines = lyrics
+            .get_timed_lines()
+            .into_iter()
+            .map(|line| (line.text, line.start_time, line.end_time))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                ("first".to_string(), ms(1000), ms(3500)),
+                // an empty line ends the one before it
+                ("chorus".to_string(), ms(3500), ms(5000)),
+                ("second".to_string(), ms(6250), ms(10_000)),
+                // the last line lasts until the end of the track
+                ("chorus".to_string(), ms(10_000), ms(12_000)),
+            ]
+        );
+        assert_eq!((lyrics.start_time, lyrics.end_time), (ms(1000), ms(12_000)));
+
+        assert!(Lyrics::from_lrc("[ar:Artist]\nno timestamps", ms(12_000)).is_none());
+    }
+}
@@ -1,381 +1,444 @@
 use std::{
+    io::{IsTerminal as _, Write as _},
     sync::Arc,
     time::Duration,
 };
 
-use figment::{
-    providers::{Env, Format as _, Toml},
-    Figment,
+use dyrics::{
+    config::{Config, SourceKind, CONFIG_PATH},
+    discord::{self, DiscordApi},
+    lyrics::Lyrics,
+    playback::{resync_loop, step_loop},
+    provider::{self, normalize_track_id, LyricsProvider},
+    recorder::Recorder,
+    shutdown::Shutdown,
+    sink::build_sinks,
+    spotify::{access_token, create_client, SpotifySource},
+    status::{status_loop, Planner},
+    translation::Translator,
 };
-use once_cell::sync::Lazy;
-use reqwest::Client;
 use rspotify::{
-    clients::OAuthClient as _,
-    model::{AdditionalType, FullTrack, PlayableItem},
-    scopes, AuthCodeSpotify, Credentials, OAuth,
+    clients::{BaseClient as _, OAuthClient as _},
+    model::TrackId,
 };
-use serde::Deserialize;
-use serde_json::json;
-use serde_with::serde_as;
-use serde_with::DurationSeconds;
 use tokio::sync::RwLock;
+use tracing_subscriber::EnvFilter;
 
-static DISCORD_REQWEST: Lazy<reqwest::Client> = Lazy::new(|| reqwest::Client::new());
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let verbose = args
+        .first()
+        .is_some_and(|arg| matches!(*arg, "-v" | "--verbose"));
+    if verbose {
+        args.remove(0);
+    }
+    init_logging(verbose);
 
-#[derive(Debug, Clone, Deserialize)]
-struct Config {
-    discord: DiscordConfig,
-    spotify: SpotifyConfig,
-}
+    if args == ["doctor"] {
+        // checks the config itself, so it can't be loaded up front
+        if !doctor().await {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args == ["init"] {
+        return init();
+    }
 
-#[derive(Debug, Clone, Deserialize)]
-struct DiscordConfig {
-    token: String,
-}
+    if !Config::exists() {
+        onboard()?;
+        std::process::exit(1);
+    }
 
-fn default_redirect_uri() -> String {
-    "https://127.0.0.1".to_string()
+    let config = Config::load()?;
+    match args[..] {
+        [] => run(config).await,
+        ["lyrics", track] => dump_lyrics(config, track).await,
+        ["schedule", track] => dump_schedule(config, track).await,
+        ["replay", path] => dyrics::replay::replay(&config, path.as_ref()).await,
+        ["verify-discord"] => {
+            if !verify_discord(config).await {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        _ => {
+            eprintln!("Usage: dyrics [--verbose] [init|doctor|verify-discord|lyrics <track>|schedule <track>|replay <session.jsonl>], where <track> is a spotify track url, uri or id. RUST_LOG takes a filter like \"dyrics=debug\" for finer control over the log");
+            std::process::exit(2);
+        }
+    }
 }
 
-fn default_resync_interval() -> Duration {
-    Duration::from_secs_f32(2.5)
+/// Logs to stderr, filtered by `RUST_LOG` if it is set. Otherwise only
+/// warnings are logged, or everything dyrics logs with `--verbose`.
+fn init_logging(verbose: bool) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(if verbose { "warn,dyrics=debug" } else { "warn" }));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
 }
 
-#[serde_as]
-#[derive(Debug, Clone, Deserialize)]
-struct SpotifyConfig {
-    client_id: String,
-    client_secret: String,
-    #[serde(default = "default_redirect_uri")]
-    redirect_uri: String,
-    #[serde_as(as = "DurationSeconds<f64>")]
-    #[serde(default = "default_resync_interval")]
-    resync_interval: Duration,
+/// What has to be filled in before dyrics can run.
+const REQUIRED_OPTIONS: &str = concat!(
+    "  spotify.client_id      from your app on https://developer.spotify.com/dashboard\n",
+    "  spotify.client_secret  from the same app\n",
+    "  discord.token          the token of the account to show the lyrics on",
+);
+
+/// Writes a config to start from, without overwriting an existing one.
+fn init() -> eyre::Result<()> {
+    let mut file = match std::fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(CONFIG_PATH)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+            eyre::bail!("{} already exists, not overwriting it", CONFIG_PATH)
+        }
+        Err(err) => return Err(err.into()),
+    };
+    file.write_all(Config::template().as_bytes())?;
+
+    println!(
+        "Wrote {}, fill in these before running dyrics:",
+        CONFIG_PATH
+    );
+    println!("{}", REQUIRED_OPTIONS);
+    println!("Every other option is commented out with its default.");
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> eyre::Result<()> {
-    let config = Figment::new()
-        .merge(Toml::file("config.toml"))
-        .merge(Env::prefixed("CONFIG_"))
-        .extract::<Config>()?;
-
-    let spotify = AuthCodeSpotify::new(
-        Credentials::new(&config.spotify.client_id, &config.spotify.client_secret),
-        OAuth {
-            redirect_uri: config.spotify.redirect_uri,
-            scopes: scopes!("user-read-currently-playing"),
-            ..Default::default()
-        },
+/// Explains what is missing on the first run, offering to write a config to
+/// start from when there is someone to ask.
+fn onboard() -> eyre::Result<()> {
+    eprintln!(
+        "No {} found and no CONFIG_ environment variables set. dyrics needs at least:",
+        CONFIG_PATH
     );
-    spotify
-        .prompt_for_token(&spotify.get_authorize_url(false).unwrap())
-        .await
-        .unwrap();
+    eprintln!("{}", REQUIRED_OPTIONS);
+
+    if !std::io::stdin().is_terminal() {
+        eprintln!(
+            "Run \"dyrics init\" to write a {} to start from.",
+            CONFIG_PATH
+        );
+        return Ok(());
+    }
+    eprint!("Write a {} to start from now? [y/N] ", CONFIG_PATH);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if answer.trim().eq_ignore_ascii_case("y") {
+        init()?;
+    }
+    Ok(())
+}
 
-    let current_lyrics = Arc::new(RwLock::new(Option::None));
+async fn run(config: Config) -> eyre::Result<()> {
+    let recorder = Recorder::new(config.recorder.as_ref())?;
+    let sinks = build_sinks(&config, &recorder)?;
+
+    if config.discord.clear_on_start {
+        let client = discord::http_client(&config.discord)?;
+        for token in &config.discord.tokens {
+            DiscordApi::new(client.clone(), &config.discord, token)
+                .clear_status(config.discord.clear_payload)
+                .await?;
+        }
+    }
 
-    tokio::spawn(step_loop(current_lyrics.clone()));
+    let spotify = create_client(&config.spotify).await?;
+
+    let current_playback = Arc::new(RwLock::new(None));
+    let provider = lyrics_provider(&config)?;
+
+    // only now, the spotify login may still have been waiting on Ctrl+C
+    let shutdown = Shutdown::on_ctrl_c();
+    let stepping = config.spotify.use_stepping.then(|| {
+        tokio::spawn(step_loop(
+            current_playback.clone(),
+            config.spotify.stale_after,
+            shutdown.clone(),
+        ))
+    });
+
+    let resync = async {
+        match config.source {
+            SourceKind::Spotify => {
+                let source = SpotifySource::new(spotify, &config.spotify);
+                resync_loop(
+                    current_playback.clone(),
+                    source,
+                    provider,
+                    config.clone(),
+                    recorder.clone(),
+                    shutdown.clone(),
+                )
+                .await
+            }
+            #[cfg(feature = "mpris")]
+            SourceKind::Mpris => {
+                let source = dyrics::mpris::MprisSource::new(spotify).await?;
+                resync_loop(
+                    current_playback.clone(),
+                    source,
+                    provider,
+                    config.clone(),
+                    recorder.clone(),
+                    shutdown.clone(),
+                )
+                .await
+            }
+            // rejected when the config is loaded
+            #[cfg(not(feature = "mpris"))]
+            SourceKind::Mpris => unreachable!(),
+        }
+    };
 
     tokio::try_join!(
-        resync_loop(
-            current_lyrics.clone(),
-            spotify,
-            config.spotify.resync_interval
+        resync,
+        status_loop(
+            current_playback.clone(),
+            sinks,
+            &config,
+            recorder.clone(),
+            shutdown.clone()
         ),
-        status_loop(current_lyrics.clone(), &config.discord.token),
     )?;
+    if let Some(stepping) = stepping {
+        stepping.await?;
+    }
 
     Ok(())
 }
 
-#[serde_as]
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-struct Lyrics {
-    #[serde_as(as = "DurationSeconds<f64>")]
-    start_time: Duration,
-    #[serde_as(as = "DurationSeconds<f64>")]
-    end_time: Duration,
-    #[serde(flatten)]
-    content: LyricsContent,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "PascalCase", tag = "Type", content = "Content")]
-enum LyricsContent {
-    Syllable(Vec<SyllableLyricsLine>),
-    Line(Vec<LineLyricsLine>),
-    //Static(), TODO: Fix, we need not "content" but "lines" for this
+/// The lyrics provider, translating lyrics if `[translation]` is set up.
+fn lyrics_provider(config: &Config) -> eyre::Result<LyricsProvider> {
+    let client = provider::http_client(&config.lyrics)?;
+    let provider = LyricsProvider::new(client.clone(), &config.lyrics);
+    Ok(match config.translation {
+        Some(ref translation) => provider.with_translator(Translator::new(client, translation)),
+        None => provider,
+    })
 }
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-struct SyllableLyricsLine {
-    r#type: String,
-    opposite_aligned: bool,
-    lead: SyllableLyricsLead,
+fn parse_track_id(track: &str) -> eyre::Result<String> {
+    normalize_track_id(track)
+        .ok_or_else(|| eyre::eyre!("\"{}\" is not a spotify track url, uri or id", track))
 }
 
-#[serde_as]
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-struct SyllableLyricsLead {
-    syllables: Vec<SyllableLyricsSyllable>,
-    #[serde_as(as = "DurationSeconds<f64>")]
-    start_time: Duration,
-    #[serde_as(as = "DurationSeconds<f64>")]
-    end_time: Duration,
-}
+/// Prints what the lyrics provider returns for a track, to help debug schema problems.
+async fn dump_lyrics(config: Config, track: &str) -> eyre::Result<()> {
+    let track_id = parse_track_id(track)?;
 
-#[serde_as]
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-struct SyllableLyricsSyllable {
-    text: String,
-    is_part_of_word: bool,
-    #[serde_as(as = "DurationSeconds<f64>")]
-    start_time: Duration,
-    #[serde_as(as = "DurationSeconds<f64>")]
-    end_time: Duration,
-}
+    let spotify = create_client(&config.spotify).await?;
+    let provider = LyricsProvider::new(provider::http_client(&config.lyrics)?, &config.lyrics);
+    let (status, body) = provider
+        .fetch_raw(&track_id, &access_token(&spotify).await?)
+        .await?;
 
-#[serde_as]
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-struct LineLyricsLine {
-    r#type: String,
-    opposite_aligned: bool,
-    text: String,
-    #[serde_as(as = "DurationSeconds<f64>")]
-    start_time: Duration,
-    #[serde_as(as = "DurationSeconds<f64>")]
-    end_time: Duration,
-}
+    println!("Provider responded with {}", status);
+    match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(json) => println!("{}", serde_json::to_string_pretty(&json)?),
+        Err(_) => println!("{}", body),
+    }
 
-async fn step_loop(current_lyrics: Arc<RwLock<Option<(Option<Lyrics>, FullTrack, Duration)>>>) {
-    loop {
-        if let Some(ref mut tuple) = *current_lyrics.write().await {
-            tuple.2 = tuple.2 + Duration::from_millis(50);
-        }
-        tokio::time::sleep(Duration::from_millis(50)).await
+    match serde_json::from_str::<Lyrics>(&body) {
+        Ok(lyrics) => println!("{:#?}", lyrics),
+        Err(err) => println!("Failed to parse lyrics: {}", err),
     }
+
+    Ok(())
 }
 
-async fn resync_loop(
-    current_lyrics: Arc<RwLock<Option<(Option<Lyrics>, FullTrack, Duration)>>>,
-    spotify: AuthCodeSpotify,
-    resync_interval: Duration,
-) -> eyre::Result<()> {
-    let mut last_playing = None;
-    let reqwest = Client::new();
-
-    loop {
-        if let Some(currently_playing) = spotify
-            .current_playing(None, None::<Vec<&AdditionalType>>)
-            .await?
-        {
-            if !currently_playing.is_playing || currently_playing.item.is_none() {
-                last_playing = None;
-                *current_lyrics.write().await = None;
-                tokio::time::sleep(resync_interval).await;
-                continue;
-            }
+/// Prints the updates a whole song would get as JSON, without sending any.
+async fn dump_schedule(config: Config, track: &str) -> eyre::Result<()> {
+    let track_id = parse_track_id(track)?;
 
-            if let PlayableItem::Track(track) = currently_playing.item.unwrap() {
-                if track.id != last_playing {
-                    last_playing = track.id.clone();
-
-                    if let Some(ref track_id) = track.id {
-                        let response = reqwest
-                            .get(format!(
-                                "https://beautiful-lyrics.socalifornian.live/lyrics/{}",
-                                track_id.to_string().replace("spotify:track:", "")
-                            ))
-                            .bearer_auth(
-                                spotify
-                                    .token
-                                    .lock()
-                                    .await
-                                    .unwrap()
-                                    .clone()
-                                    .unwrap()
-                                    .access_token,
-                            )
-                            .send()
-                            .await?;
-                        let lyrics = response.json().await;
-
-                        if let Ok(lyrics) = lyrics {
-                            *current_lyrics.write().await = Some((
-                                Some(lyrics),
-                                track.clone(),
-                                Duration::from_millis(
-                                    currently_playing.progress.unwrap().num_milliseconds() as u64,
-                                ),
-                            ))
-                        } else {
-                            *current_lyrics.write().await = Some((
-                                None,
-                                track.clone(),
-                                Duration::from_millis(
-                                    currently_playing.progress.unwrap().num_milliseconds() as u64,
-                                ),
-                            ))
-                        }
-                    }
-                } else {
-                    // only update the timestamp
-                    if let Some(ref mut tuple) = *current_lyrics.write().await {
-                        tuple.2 = Duration::from_millis(
-                            currently_playing.progress.unwrap().num_milliseconds() as u64,
-                        )
-                    }
-                }
-            } else {
-                last_playing = None;
-                *current_lyrics.write().await = None;
-                tokio::time::sleep(resync_interval).await;
-                continue;
-            }
-        }
+    let spotify = create_client(&config.spotify).await?;
+    let track = spotify.track(TrackId::from_id(&track_id)?, None).await?;
+    let provider = lyrics_provider(&config)?;
+    let mut lyrics = provider
+        .fetch_lyrics(&track, &access_token(&spotify).await?)
+        .await?
+        .ok_or_else(|| eyre::eyre!("no lyrics found for {}", track_id))?;
+    lyrics.shift(provider::lyrics_offset(&config.lyrics, &track));
 
-        tokio::time::sleep(resync_interval).await;
-    }
+    let schedule = Planner::from_config(&config)?.schedule(&lyrics, &track, Duration::ZERO);
+    println!("{}", serde_json::to_string_pretty(&schedule)?);
+
+    Ok(())
 }
 
-async fn status_loop(
-    current_lyrics: Arc<RwLock<Option<(Option<Lyrics>, FullTrack, Duration)>>>,
-    token: &str,
-) -> eyre::Result<()> {
-    let mut last_text = None;
-
-    loop {
-        if let Some((current_lyrics, track, current_time)) = current_lyrics.read().await.clone() {
-            let text = if let Some(lyrics) = current_lyrics {
-                match lyrics.content {
-                    LyricsContent::Syllable(syllables) => {
-                        let syllable = syllable_find_nearest(&syllables, current_time);
-
-                        if let Some(syllable) = syllable {
-                            syllable
-                                .lead
-                                .syllables
-                                .iter()
-                                .map(|val| val.text.to_string())
-                                .collect::<Vec<_>>()
-                                .join(" ")
-                        } else {
-                            "".to_string()
-                        }
-                    }
-                    LyricsContent::Line(lines) => {
-                        let line = line_find_nearest(&lines, current_time);
-
-                        line.map(|val| val.text.to_string())
-                            .unwrap_or("".to_string())
-                    }
-                }
-            } else {
-                format!(
-                    "{} - {}",
-                    track.name,
-                    track
-                        .artists
-                        .iter()
-                        .map(|val| val.name.to_string())
-                        .collect::<Vec<_>>()
-                        .join(",")
-                )
-            };
+/// A track the lyrics provider is known to have lyrics for.
+const DOCTOR_TRACK_ID: &str = "4cOdK2wGLETKBW3PvgPWqT";
 
-            if let Some(ref last_text_loc) = last_text {
-                if last_text_loc != &text {
-                    set_discord_status(&text, "🎶", token).await?;
-                    println!("New text is: {}", text);
-                    last_text = Some(text);
-                }
-            } else {
-                set_discord_status(&text, "🎶", token).await?;
-                println!("New text is: {}", text);
-                last_text = Some(text);
-            }
-        } else {
-            if last_text.is_some() {
-                set_discord_status("", "", token).await?;
-                last_text = None;
-            }
+/// Prints how a check went, with a hint on what to do if it failed. Returns
+/// whether it passed.
+fn report<T, E: std::fmt::Display>(check: &str, result: &Result<T, E>, hint: &str) -> bool {
+    match result {
+        Ok(_) => {
+            println!("[ok]   {}", check);
+            true
+        }
+        Err(err) => {
+            println!("[FAIL] {}: {}", check, err);
+            println!("       {}", hint);
+            false
         }
-        tokio::time::sleep(Duration::from_micros(300)).await;
     }
 }
 
-fn syllable_contains_duration(item: &SyllableLyricsLead, duration: Duration) -> bool {
-    item.start_time <= duration && duration <= item.end_time
-}
-
-fn syllable_distance_to(item: &SyllableLyricsLead, duration: Duration) -> Duration {
-    if duration < item.start_time {
-        item.start_time - duration
-    } else if duration > item.end_time {
-        duration - item.end_time
-    } else {
-        Duration::from_secs(0)
+/// Checks that everything dyrics depends on can be reached with the config,
+/// and returns whether it all worked.
+async fn doctor() -> bool {
+    let config = Config::load();
+    if !report(
+        "config loads",
+        &config,
+        "check config.toml against config.example.toml",
+    ) {
+        return false;
     }
-}
-
-fn syllable_find_nearest<'a>(
-    items: &'a [SyllableLyricsLine],
-    target: Duration,
-) -> Option<&'a SyllableLyricsLine> {
-    items.iter().min_by_key(|item| {
-        if syllable_contains_duration(&item.lead, target) {
-            Duration::from_secs(0)
-        } else {
-            syllable_distance_to(&item.lead, target)
+    let Ok(config) = config else {
+        return false;
+    };
+    let mut passed = true;
+
+    let spotify = create_client(&config.spotify).await;
+    passed &= report(
+        "spotify login",
+        &spotify,
+        match config.spotify.token_command {
+            Some(_) => "check that spotify.token_command prints a valid access token",
+            None => "check spotify.client_id, client_secret and redirect_uri",
+        },
+    );
+    let token = match spotify {
+        Ok(ref spotify) => {
+            let playing = spotify.current_playing(None, None::<Vec<_>>).await;
+            passed &= report(
+                "spotify playback",
+                &playing,
+                "check that your account is added to the spotify app and spotify.scopes",
+            );
+            access_token(spotify).await.ok()
         }
-    })
-}
-
-fn line_contains_duration(line: &LineLyricsLine, duration: Duration) -> bool {
-    line.start_time <= duration && duration <= line.end_time
-}
+        Err(_) => None,
+    };
+
+    match token {
+        Some(token) => {
+            let lyrics = match provider::http_client(&config.lyrics) {
+                Ok(client) => LyricsProvider::new(client, &config.lyrics)
+                    .fetch_raw(DOCTOR_TRACK_ID, &token)
+                    .await
+                    .and_then(|(status, _)| {
+                        status
+                            .is_success()
+                            .then_some(())
+                            .ok_or_else(|| eyre::eyre!("the provider responded with {}", status))
+                    }),
+                Err(err) => Err(err),
+            };
+            passed &= report(
+                "lyrics provider",
+                &lyrics,
+                "check your connection and lyrics.http_proxy, the provider may also be down",
+            );
+        }
+        None => println!("[skip] lyrics provider: needs the spotify login"),
+    }
 
-fn line_distance_to(line: &LineLyricsLine, duration: Duration) -> Duration {
-    if duration < line.start_time {
-        line.start_time - duration
-    } else if duration > line.end_time {
-        duration - line.end_time
-    } else {
-        Duration::from_secs(0)
+    if config.discord.tokens.is_empty() {
+        println!("[skip] discord: no discord.token");
+    }
+    for (index, token) in config.discord.tokens.iter().enumerate() {
+        let check = match discord::http_client(&config.discord) {
+            Ok(client) => {
+                DiscordApi::new(client, &config.discord, token)
+                    .check_token()
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        passed &= report(
+            &format!("discord token {}", index + 1),
+            &check,
+            "get a fresh token from the Discord client, it changes when you log out",
+        );
     }
+
+    passed
 }
 
-fn line_find_nearest<'a>(
-    lines: &'a [LineLyricsLine],
-    target: Duration,
-) -> Option<&'a LineLyricsLine> {
-    lines.iter().min_by_key(|line| {
-        if line_contains_duration(line, target) {
-            Duration::from_secs(0)
-        } else {
-            line_distance_to(line, target)
+/// Sets a status on every account, reads it back and puts the one from before
+/// back. Unlike [`doctor`] this changes the status for a moment, so it isn't
+/// part of it.
+async fn verify_discord(config: Config) -> bool {
+    let client = match discord::http_client(&config.discord) {
+        Ok(client) => client,
+        Err(err) => {
+            return report(
+                "discord client",
+                &Err::<(), _>(err),
+                "check discord.http_proxy",
+            )
         }
-    })
-}
+    };
+    if config.discord.tokens.is_empty() {
+        println!("[skip] discord: no discord.token");
+    }
 
-async fn set_discord_status(text: &str, emoji: &str, token: &str) -> eyre::Result<()> {
-    DISCORD_REQWEST
-        .patch("https://discord.com/api/v6/users/@me/settings")
-        .header("authorization", token)
-        .json(&json!({
-            "custom_status": {
-                "text": text,
-                "emoji_name": emoji
+    let text = format!("dyrics test {}", chrono::Utc::now().timestamp());
+    let mut passed = true;
+    for (index, token) in config.discord.tokens.iter().enumerate() {
+        let api = DiscordApi::new(client.clone(), &config.discord, token);
+        let check = format!("discord status {}", index + 1);
+        let previous = match api.custom_status().await {
+            Ok(previous) => previous,
+            Err(err) => {
+                passed &= report(
+                    &check,
+                    &Err::<(), _>(err),
+                    "get a fresh token from the Discord client, it changes when you log out",
+                );
+                continue;
             }
-        }))
-        .send()
-        .await?;
+        };
+
+        let round_trip = async {
+            api.send_status(&text, &config.display.emoji, None).await?;
+            let status = api.custom_status().await?;
+            match status.get("text").and_then(|text| text.as_str()) {
+                Some(sent) if sent == text => Ok(()),
+                sent => Err(eyre::eyre!(
+                    "sent {:?} but read back {:?}",
+                    text,
+                    sent.unwrap_or_default()
+                )),
+            }
+        }
+        .await;
+        passed &= report(
+            &check,
+            &round_trip,
+            "check discord.api_version, Discord may also have changed the settings endpoint",
+        );
+
+        // the status from before is put back no matter how it went
+        let restored = api.restore_status(&previous).await;
+        passed &= report(
+            &format!("discord status {} restored", index + 1),
+            &restored,
+            "set your status again by hand",
+        );
+    }
 
-    Ok(())
+    passed
 }
@@ -0,0 +1,160 @@
+use std::{collections::HashMap, time::Duration};
+
+use async_trait::async_trait;
+use rspotify::{
+    clients::BaseClient as _,
+    model::{FullTrack, Market, TrackId},
+    AuthCodeSpotify,
+};
+use tokio::sync::Mutex;
+use zbus::{
+    fdo::DBusProxy,
+    proxy::{Builder, CacheProperties},
+    zvariant::{OwnedValue, Value},
+    Connection, Proxy,
+};
+
+use crate::{
+    provider::normalize_track_id,
+    source::{PlaybackSource, PlaybackStatus},
+    spotify::access_token,
+};
+
+const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// Reads what is playing from a local player over MPRIS, e.g. the Spotify
+/// client or spotifyd. The Web API is only asked for a track's details when
+/// it changes.
+pub struct MprisSource {
+    connection: Connection,
+    spotify: AuthCodeSpotify,
+    /// The last track looked up through the Web API, by the id it was
+    /// looked up with
+    track: Mutex<Option<(String, FullTrack)>>,
+}
+
+impl MprisSource {
+    pub async fn new(spotify: AuthCodeSpotify) -> eyre::Result<Self> {
+        Ok(Self {
+            connection: Connection::session().await?,
+            spotify,
+            track: Mutex::new(None),
+        })
+    }
+
+    /// Bus names of the running players, Spotify ones first.
+    async fn players(&self) -> eyre::Result<Vec<String>> {
+        let mut players = DBusProxy::new(&self.connection)
+            .await?
+            .list_names()
+            .await?
+            .into_iter()
+            .map(|name| name.to_string())
+            .filter(|name| name.starts_with(PLAYER_PREFIX))
+            .collect::<Vec<_>>();
+        players.sort_by_key(|name| !name.to_lowercase().contains("spotify"));
+
+        Ok(players)
+    }
+
+    async fn player(&self, name: String) -> eyre::Result<Proxy<'static>> {
+        Ok(Builder::new(&self.connection)
+            .destination(name)?
+            .path(PLAYER_PATH)?
+            .interface(PLAYER_INTERFACE)?
+            // the position changes without anyone being told
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+
+    /// The Spotify track the player is playing and where in it, or `None`
+    /// if it is paused or playing something else.
+    async fn read_player(&self, name: String) -> eyre::Result<Option<(String, Duration)>> {
+        let player = self.player(name).await?;
+        let metadata = player
+            .get_property::<HashMap<String, OwnedValue>>("Metadata")
+            .await?;
+        let Some(track_id) = spotify_track_id(&metadata) else {
+            return Ok(None);
+        };
+        if player.get_property::<String>("PlaybackStatus").await? != "Playing" {
+            return Ok(None);
+        }
+
+        // in microseconds
+        let position = player.get_property::<i64>("Position").await?;
+        Ok(Some((
+            track_id,
+            Duration::from_micros(position.max(0) as u64),
+        )))
+    }
+
+    async fn full_track(&self, track_id: &str) -> eyre::Result<FullTrack> {
+        let mut cached = self.track.lock().await;
+        match *cached {
+            Some((ref id, ref track)) if id == track_id => return Ok(track.clone()),
+            _ => {}
+        }
+
+        let track = self
+            .spotify
+            .track(TrackId::from_id(track_id)?, Some(Market::FromToken))
+            .await?;
+        *cached = Some((track_id.to_string(), track.clone()));
+
+        Ok(track)
+    }
+}
+
+/// The Spotify track id in a player's metadata, if it is playing one.
+fn spotify_track_id(metadata: &HashMap<String, OwnedValue>) -> Option<String> {
+    let string = |key: &str| match metadata.get(key).map(|value| &**value) {
+        Some(Value::Str(value)) => Some(value.to_string()),
+        Some(Value::ObjectPath(value)) => Some(value.to_string()),
+        _ => None,
+    };
+
+    // e.g. "https://open.spotify.com/track/<id>" or "/com/spotify/track/<id>"
+    string("xesam:url")
+        .and_then(|url| normalize_track_id(&url))
+        .or_else(|| {
+            string("mpris:trackid")
+                .filter(|path| path.contains("spotify"))
+                .and_then(|path| normalize_track_id(path.rsplit('/').next()?))
+        })
+}
+
+#[async_trait]
+impl PlaybackSource for MprisSource {
+    async fn poll(&self) -> eyre::Result<Option<PlaybackStatus>> {
+        let players = self.players().await?;
+        if players.is_empty() {
+            return Ok(Some(PlaybackStatus::Stopped));
+        }
+
+        // spotify players come first, the first one that is playing wins
+        for name in players {
+            // a player that can't be read, e.g. because it just exited or
+            // doesn't have a position, shouldn't hide the ones after it
+            let Ok(Some((track_id, position))) = self.read_player(name).await else {
+                continue;
+            };
+
+            return Ok(Some(PlaybackStatus::Playing {
+                track: Box::new(self.full_track(&track_id).await?),
+                position,
+                context: None,
+            }));
+        }
+
+        // either paused or only playing things that aren't from spotify
+        Ok(Some(PlaybackStatus::Stopped))
+    }
+
+    async fn access_token(&self) -> eyre::Result<String> {
+        access_token(&self.spotify).await
+    }
+}
@@ -0,0 +1,93 @@
+use async_trait::async_trait;
+use notify_rust::{Notification, Timeout};
+
+use crate::{
+    config::NotificationConfig,
+    error::DyricsError,
+    playback::CurrentPlayback,
+    sink::{prepend_emoji, StatusSink},
+    status::TrackFormat,
+};
+
+/// Pops up a desktop notification for every new line, replacing the previous
+/// one where the platform allows it.
+pub struct NotificationSink {
+    timeout: Timeout,
+    title: String,
+    track_format: TrackFormat,
+    prepend_emoji: bool,
+    last_sent: Option<String>,
+    last_id: Option<u32>,
+}
+
+impl NotificationSink {
+    pub fn new(config: &NotificationConfig, track_format: TrackFormat) -> Self {
+        Self {
+            timeout: Timeout::Milliseconds(config.timeout.as_millis() as u32),
+            title: "dyrics".to_string(),
+            track_format,
+            prepend_emoji: config.prepend_emoji,
+            last_sent: None,
+            last_id: None,
+        }
+    }
+}
+
+#[async_trait]
+impl StatusSink for NotificationSink {
+    fn name(&self) -> &str {
+        "notification"
+    }
+
+    fn set_playback(&mut self, playback: &CurrentPlayback) {
+        self.title = self.track_format.render(
+            &playback.track,
+            playback.context.as_deref(),
+            playback.position,
+        );
+    }
+
+    async fn send_update(&mut self, text: &str, emoji: &str) -> Result<bool, DyricsError> {
+        if self.last_sent.as_deref() == Some(text) {
+            return Ok(false);
+        }
+
+        let mut notification = Notification::new();
+        notification
+            .appname("dyrics")
+            .summary(&self.title)
+            .body(&if self.prepend_emoji {
+                prepend_emoji(text, emoji)
+            } else {
+                text.to_string()
+            })
+            .timeout(self.timeout);
+        if let Some(id) = self.last_id {
+            notification.id(id);
+        }
+
+        // showing blocks on the platform's notification service
+        let handle = tokio::task::spawn_blocking(move || notification.show())
+            .await
+            .map_err(|err| DyricsError::Notification(err.to_string()))?
+            .map_err(|err| DyricsError::Notification(err.to_string()))?;
+
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            self.last_id = Some(handle.id());
+        }
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        drop(handle);
+
+        self.last_sent = Some(text.to_string());
+
+        Ok(true)
+    }
+
+    async fn clear_status(&mut self) -> Result<(), DyricsError> {
+        self.last_sent = None;
+        self.last_id = None;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,591 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use rspotify::{
+    model::{FullTrack, TrackId},
+    prelude::Id as _,
+    ClientError,
+};
+use tokio::{sync::RwLock, task::JoinHandle, time::error::Elapsed};
+
+use crate::{
+    config::{Config, SpotifyConfig},
+    lyrics::Lyrics,
+    provider::{lyrics_offset, lyrics_track_ids, LyricsProvider},
+    recorder::{Event, Recorder},
+    shutdown::Shutdown,
+    source::{PlaybackSource, PlaybackStatus},
+    spotify::{self, is_temporary_error},
+};
+
+/// How often [`step_loop`] moves the position forward.
+pub const STEP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often the lyrics of a track are tried before it is shown without them.
+const MAX_LYRICS_ATTEMPTS: u32 = 3;
+
+/// Longest wait between retries while Spotify is unavailable.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct CurrentPlayback {
+    pub lyrics: Option<Lyrics>,
+    pub track: FullTrack,
+    pub position: Duration,
+    pub duration: Duration,
+    /// What the track is played from, see [`PlaybackStatus::Playing`]
+    pub context: Option<String>,
+    /// Bumped by [`sync_once`] whenever the track changes
+    pub generation: u64,
+    /// When the position was last synced
+    pub synced_at: Instant,
+}
+
+impl CurrentPlayback {
+    /// Whether the position went without a sync for so long that it can't
+    /// be trusted anymore.
+    pub fn is_stale(&self, stale_after: Duration) -> bool {
+        self.synced_at.elapsed() > stale_after
+    }
+}
+
+pub type SharedPlayback = Arc<RwLock<Option<CurrentPlayback>>>;
+
+/// Moves the position along between syncs, until `stale_after` has passed
+/// without one.
+pub async fn step_loop(
+    current_playback: SharedPlayback,
+    stale_after: Duration,
+    mut shutdown: Shutdown,
+) {
+    loop {
+        if let Some(ref mut playback) = *current_playback.write().await {
+            if !playback.is_stale(stale_after) {
+                // once the end is reached, wait for the next sync to move on
+                playback.position = (playback.position + STEP_INTERVAL).min(playback.duration);
+            }
+        }
+        if !shutdown.sleep(STEP_INTERVAL).await {
+            return;
+        }
+    }
+}
+
+/// What [`sync_once`] remembers between syncs.
+#[derive(Debug, Default)]
+pub struct SyncState {
+    pub last_track_id: Option<TrackId<'static>>,
+    pub generation: u64,
+    /// A different track that showed up, and in how many syncs in a row
+    pub pending_track: Option<(Option<TrackId<'static>>, u32)>,
+    /// In how many syncs in a row nothing was playing
+    pub stopped_syncs: u32,
+    /// The lyrics fetch for the current track, while it is going on
+    pub fetch: Option<PendingFetch>,
+    /// How often fetching the current track's lyrics failed
+    pub lyrics_failures: u32,
+}
+
+impl SyncState {
+    /// Drops the lyrics fetch for the track before, if it is still going on.
+    fn cancel_fetch(&mut self) {
+        if let Some(fetch) = self.fetch.take() {
+            fetch.task.abort();
+        }
+    }
+}
+
+/// Lyrics being fetched in the background, so that skipping through tracks
+/// can drop the fetches of the tracks skipped past.
+#[derive(Debug)]
+pub struct PendingFetch {
+    generation: u64,
+    track: FullTrack,
+    context: Option<String>,
+    task: JoinHandle<eyre::Result<Option<Lyrics>>>,
+}
+
+pub async fn resync_loop<S: PlaybackSource>(
+    current_playback: SharedPlayback,
+    source: S,
+    provider: LyricsProvider,
+    config: Config,
+    recorder: Recorder,
+    mut shutdown: Shutdown,
+) -> eyre::Result<()> {
+    let provider = Arc::new(provider);
+    let mut state = SyncState::default();
+    let mut failures = 0;
+    // syncs left at `resync_interval_after_change`
+    let mut fast_syncs = 0;
+
+    loop {
+        let generation = state.generation;
+        let result = tokio::select! {
+            result = sync_once(&current_playback, &source, &provider, &config, &recorder, &mut state) => result,
+            _ = shutdown.requested() => return Ok(()),
+        };
+        // a change that hasn't stuck around long enough yet counts too, the
+        // sooner the next sync the sooner it does
+        if state.generation != generation || state.pending_track.is_some() {
+            fast_syncs = config.spotify.syncs_after_change;
+        }
+        if let Err(ref err) = result {
+            recorder.record(|| Event::Error {
+                context: "sync".to_string(),
+                message: format!("{:#}", err),
+            });
+        }
+        let delay =
+            match result {
+                Ok(()) => {
+                    failures = 0;
+                    next_sync_delay(&config.spotify, &mut fast_syncs)
+                }
+                Err(err) if is_login_rejected(&err) => return Err(err.wrap_err(
+                    "spotify no longer accepts the login, check that dyrics still has access to \
+                     your account and restart it to log in again",
+                )),
+                Err(err) if is_temporary(&err) => {
+                    // the last playback keeps stepping along in the meantime
+                    failures += 1;
+                    let delay = retry_delay(config.spotify.sync_interval(), failures);
+                    tracing::warn!("{}, retrying in {:.1}s", err, delay.as_secs_f32());
+                    delay
+                }
+                Err(err) => return Err(err),
+            };
+
+        if !shutdown.sleep(delay).await {
+            return Ok(());
+        }
+    }
+}
+
+/// The wait after a sync that went through, `resync_interval_after_change`
+/// while there are `fast_syncs` left and the usual interval after.
+fn next_sync_delay(config: &SpotifyConfig, fast_syncs: &mut u32) -> Duration {
+    match config.resync_interval_after_change {
+        Some(interval) if *fast_syncs > 0 => {
+            *fast_syncs -= 1;
+            interval.min(config.sync_interval())
+        }
+        _ => config.sync_interval(),
+    }
+}
+
+/// Doubles the interval with every failure in a row, up to [`MAX_RETRY_DELAY`].
+fn retry_delay(interval: Duration, failures: u32) -> Duration {
+    (interval * 2u32.pow(failures.saturating_sub(1).min(6))).min(MAX_RETRY_DELAY)
+}
+
+/// Whether a sync failed for reasons that should pass, like timeouts, an
+/// outage or an access token that expired. Check [`is_login_rejected`] first.
+fn is_temporary(err: &eyre::Report) -> bool {
+    err.chain().any(|cause| {
+        cause.is::<Elapsed>()
+            || cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|err| err.is_timeout() || err.is_connect())
+            || cause
+                .downcast_ref::<ClientError>()
+                .is_some_and(|err| is_temporary_error(err) || spotify::is_request_rejected(err))
+    })
+}
+
+fn is_login_rejected(err: &eyre::Report) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<ClientError>()
+            .is_some_and(spotify::is_login_rejected)
+    })
+}
+
+#[tracing::instrument(
+    name = "lyrics_fetch",
+    skip_all,
+    fields(track_id = track.id.as_ref().map(|id| id.id()).unwrap_or_default())
+)]
+async fn load_lyrics(
+    provider: Arc<LyricsProvider>,
+    settle: Duration,
+    check_availability: bool,
+    track: FullTrack,
+    access_token: String,
+) -> eyre::Result<Option<Lyrics>> {
+    // skipping on in the meantime aborts this before anything is fetched
+    tokio::time::sleep(settle).await;
+
+    if check_availability {
+        for track_id in lyrics_track_ids(&track) {
+            // remembers the track as missing, which the fetch picks up on
+            match provider.has_lyrics(track_id, &access_token).await {
+                Ok(true) => break,
+                Ok(false) => {}
+                // the fetch finds out for itself
+                Err(err) => tracing::warn!("Failed to check for lyrics: {:#}", err),
+            }
+        }
+    }
+
+    // logged here to end up in this span, the sync only records it
+    provider
+        .fetch_lyrics(&track, &access_token)
+        .await
+        .inspect_err(|err| tracing::warn!("Failed to fetch the lyrics: {:#}", err))
+}
+
+pub async fn sync_once<S: PlaybackSource>(
+    current_playback: &SharedPlayback,
+    source: &S,
+    provider: &Arc<LyricsProvider>,
+    config: &Config,
+    recorder: &Recorder,
+    state: &mut SyncState,
+) -> eyre::Result<()> {
+    let polled = source.poll().await?;
+    let polled_at = Instant::now();
+    let (track, position, context) = match polled {
+        // can't tell what is playing, see `PlaybackSource::poll`
+        None => return Ok(()),
+        Some(PlaybackStatus::Stopped) => {
+            // spotify sometimes reports nothing for a moment mid-song
+            state.stopped_syncs += 1;
+            if state.stopped_syncs < config.spotify.stop_syncs {
+                return Ok(());
+            }
+            state.last_track_id = None;
+            state.cancel_fetch();
+            *current_playback.write().await = None;
+            recorder.record(|| Event::Stopped);
+            return Ok(());
+        }
+        Some(PlaybackStatus::Playing {
+            track,
+            position,
+            context,
+        }) => (*track, position, context),
+    };
+    state.stopped_syncs = 0;
+
+    if track.id == state.last_track_id {
+        state.pending_track = None;
+        recorder.record(|| Event::Position { position });
+        // only update the timestamp, the lyrics are stored along with it once
+        // they are in
+        if let Some(ref mut playback) = *current_playback.write().await {
+            playback.position = position;
+            playback.synced_at = polled_at;
+        }
+        if state.fetch.is_some() {
+            return finish_fetch(
+                current_playback,
+                config,
+                recorder,
+                state,
+                position,
+                polled_at,
+            )
+            .await;
+        }
+        if state.lyrics_failures > 0 && state.lyrics_failures < MAX_LYRICS_ATTEMPTS {
+            start_fetch(
+                source,
+                provider,
+                config,
+                state,
+                track,
+                context,
+                Duration::ZERO,
+            )
+            .await?;
+            return finish_fetch(
+                current_playback,
+                config,
+                recorder,
+                state,
+                position,
+                polled_at,
+            )
+            .await;
+        }
+        return Ok(());
+    }
+
+    // crossfades can report the next track for a moment, so a change has to
+    // stick around before it counts. Starting from nothing playing is no change.
+    if state.last_track_id.is_some() {
+        let seen = match state.pending_track {
+            Some((ref id, seen)) if *id == track.id => seen + 1,
+            _ => 1,
+        };
+        if seen < config.spotify.track_change_syncs {
+            state.pending_track = Some((track.id.clone(), seen));
+            return Ok(());
+        }
+    }
+    state.pending_track = None;
+
+    // starting from nothing playing is no skip either
+    let settle = match state.last_track_id {
+        Some(_) => config.lyrics.skip_settle,
+        None => Duration::ZERO,
+    };
+    state.last_track_id.clone_from(&track.id);
+    state.generation += 1;
+    state.lyrics_failures = 0;
+    state.cancel_fetch();
+
+    // the lyrics of the track before would keep showing until these are in
+    store_playback(
+        current_playback,
+        recorder,
+        track.clone(),
+        context.clone(),
+        None,
+        position,
+        state.generation,
+    )
+    .await;
+    if track.id.is_none() || !config.lyrics.enabled {
+        return Ok(());
+    }
+
+    start_fetch(source, provider, config, state, track, context, settle).await?;
+    finish_fetch(
+        current_playback,
+        config,
+        recorder,
+        state,
+        position,
+        polled_at,
+    )
+    .await
+}
+
+/// Fetches the lyrics for `track` in the background, for the current
+/// generation.
+async fn start_fetch<S: PlaybackSource>(
+    source: &S,
+    provider: &Arc<LyricsProvider>,
+    config: &Config,
+    state: &mut SyncState,
+    track: FullTrack,
+    context: Option<String>,
+    settle: Duration,
+) -> eyre::Result<()> {
+    let token = source.access_token().await?;
+    let task = tokio::spawn(load_lyrics(
+        provider.clone(),
+        settle,
+        config.lyrics.check_availability,
+        track.clone(),
+        token,
+    ));
+    state.fetch = Some(PendingFetch {
+        generation: state.generation,
+        track,
+        context,
+        task,
+    });
+    Ok(())
+}
+
+/// Gives the lyrics fetch a sync interval to finish and stores the playback
+/// once it has, with `position` moved along by the time since it was polled
+/// at `polled_at`. A fetch that takes longer is left to the next sync, which
+/// drops it if the track changed in the meantime.
+async fn finish_fetch(
+    current_playback: &SharedPlayback,
+    config: &Config,
+    recorder: &Recorder,
+    state: &mut SyncState,
+    position: Duration,
+    polled_at: Instant,
+) -> eyre::Result<()> {
+    let finished = match state.fetch {
+        Some(ref mut fetch) => {
+            tokio::time::timeout(config.spotify.sync_interval(), &mut fetch.task).await
+        }
+        None => return Ok(()),
+    };
+    let Ok(result) = finished else {
+        return Ok(());
+    };
+    let Some(fetch) = state.fetch.take() else {
+        return Ok(());
+    };
+    // lyrics of a track that is no longer playing would show over the wrong one
+    if fetch.generation != state.generation {
+        return Ok(());
+    }
+
+    // provider errors are logged in their span already, see `load_lyrics`,
+    // which leaves a fetch task that panicked
+    let result = result
+        .inspect_err(|err| tracing::warn!("The lyrics fetch failed: {}", err))
+        .map_err(eyre::Report::from)
+        .and_then(|lyrics| lyrics);
+    // the track is shown without lyrics rather than not at all, and the
+    // next sync tries again a few times
+    let mut lyrics = match result {
+        Ok(lyrics) => {
+            state.lyrics_failures = 0;
+            lyrics
+        }
+        Err(err) => {
+            state.lyrics_failures += 1;
+            recorder.record(|| Event::Error {
+                context: "lyrics".to_string(),
+                message: format!("{:#}", err),
+            });
+            None
+        }
+    };
+    if let Some(ref mut lyrics) = lyrics {
+        lyrics.shift(lyrics_offset(&config.lyrics, &fetch.track));
+    }
+
+    store_playback(
+        current_playback,
+        recorder,
+        fetch.track,
+        fetch.context,
+        lyrics,
+        position + polled_at.elapsed(),
+        fetch.generation,
+    )
+    .await;
+    Ok(())
+}
+
+async fn store_playback(
+    current_playback: &SharedPlayback,
+    recorder: &Recorder,
+    track: FullTrack,
+    context: Option<String>,
+    lyrics: Option<Lyrics>,
+    position: Duration,
+    generation: u64,
+) {
+    recorder.record(|| Event::Track {
+        generation,
+        track: Box::new(track.clone()),
+        context: context.clone(),
+        lyrics: lyrics.clone(),
+        position,
+    });
+    let duration = track.duration.to_std().unwrap_or_default();
+    *current_playback.write().await = Some(CurrentPlayback {
+        lyrics,
+        track,
+        position,
+        duration,
+        context,
+        generation,
+        synced_at: Instant::now(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{
+        config::ConfigBuilder,
+        provider::http_client,
+        test_util::{response, serve_after, track, LINE_LYRICS},
+    };
+
+    const TRACK_ID: &str = "4uLU6hMCjMI75M1A2tKUQC";
+
+    /// Always playing the same track at the same position.
+    struct FixedSource(Duration);
+
+    #[async_trait]
+    impl PlaybackSource for FixedSource {
+        async fn poll(&self) -> eyre::Result<Option<PlaybackStatus>> {
+            Ok(Some(PlaybackStatus::Playing {
+                track: Box::new(track(TRACK_ID)),
+                position: self.0,
+                context: None,
+            }))
+        }
+
+        async fn access_token(&self) -> eyre::Result<String> {
+            Ok("token".to_string())
+        }
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn syncs_faster_for_a_while_after_a_change() {
+        let config = SpotifyConfig {
+            resync_interval: secs(5),
+            resync_interval_after_change: Some(secs(1)),
+            syncs_after_change: 2,
+            ..SpotifyConfig::new("id", "secret")
+        };
+
+        let mut fast_syncs = config.syncs_after_change;
+        let delays = (0..3)
+            .map(|_| next_sync_delay(&config, &mut fast_syncs))
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [secs(1), secs(1), secs(5)]);
+
+        // never slower than steady playback
+        let config = SpotifyConfig {
+            resync_interval_after_change: Some(secs(10)),
+            ..config
+        };
+        assert_eq!(next_sync_delay(&config, &mut 1), secs(5));
+
+        let config = SpotifyConfig {
+            resync_interval_after_change: None,
+            ..config
+        };
+        assert_eq!(next_sync_delay(&config, &mut 1), secs(5));
+    }
+
+    #[tokio::test]
+    async fn a_slow_fetch_does_not_hold_the_lyrics_back() {
+        let delay = Duration::from_millis(300);
+        let (url, _) = serve_after(delay, |_| {
+            Some(response("200 OK", "", LINE_LYRICS.as_bytes()))
+        })
+        .await;
+        let config = ConfigBuilder::new(SpotifyConfig::new("id", "secret"))
+            .build()
+            .unwrap();
+        let provider = LyricsProvider::new(http_client(&config.lyrics).unwrap(), &config.lyrics)
+            .with_base_url(url);
+        let current_playback = SharedPlayback::default();
+        let position = secs(10);
+
+        sync_once(
+            &current_playback,
+            &FixedSource(position),
+            &Arc::new(provider),
+            &config,
+            &Recorder::new(None).unwrap(),
+            &mut SyncState::default(),
+        )
+        .await
+        .unwrap();
+
+        let playback = current_playback.read().await.clone().unwrap();
+        assert!(playback.lyrics.is_some());
+        // the track went on playing while the lyrics were fetched
+        assert!(
+            playback.position >= position + delay,
+            "{:?}",
+            playback.position
+        );
+    }
+}
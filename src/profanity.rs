@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+
+use crate::{
+    config::{MaskStyle, ProfanityConfig},
+    error::DyricsError,
+};
+
+/// Masks configured words in lyric lines, e.g. for streaming.
+#[derive(Debug, Clone)]
+pub struct ProfanityFilter {
+    words: HashSet<String>,
+    style: MaskStyle,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '\''
+}
+
+impl ProfanityFilter {
+    /// Returns `None` if filtering is disabled.
+    pub fn from_config(config: &ProfanityConfig) -> Result<Option<Self>, DyricsError> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let mut words = config
+            .words
+            .iter()
+            .map(|word| word.to_lowercase())
+            .collect::<HashSet<_>>();
+
+        if let Some(ref path) = config.words_file {
+            let contents = std::fs::read_to_string(path)?;
+            words.extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_lowercase),
+            );
+        }
+
+        Ok(Some(Self {
+            words,
+            style: config.mask,
+        }))
+    }
+
+    fn mask_word(&self, word: &str) -> String {
+        match self.style {
+            MaskStyle::Asterisks => "*".repeat(word.chars().count()),
+            MaskStyle::FirstLetter => {
+                let mut chars = word.chars();
+                let first = chars.next().map(String::from).unwrap_or_default();
+                first + &"*".repeat(chars.count())
+            }
+        }
+    }
+
+    pub fn mask(&self, text: &str) -> String {
+        let mut masked = String::with_capacity(text.len());
+        let mut word_start = None;
+
+        for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            match (word_start, index < text.len() && is_word_char(c)) {
+                (None, true) => word_start = Some(index),
+                (Some(start), false) => {
+                    let word = &text[start..index];
+                    if self.words.contains(&word.to_lowercase()) {
+                        masked.push_str(&self.mask_word(word));
+                    } else {
+                        masked.push_str(word);
+                    }
+                    word_start = None;
+                }
+                _ => {}
+            }
+
+            if word_start.is_none() && index < text.len() {
+                masked.push(c);
+            }
+        }
+
+        masked
+    }
+}
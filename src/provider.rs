@@ -0,0 +1,760 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
+
+use figment::{
+    providers::{Format as _, Toml},
+    Figment,
+};
+use reqwest::{header::HeaderMap, Client, StatusCode};
+use rspotify::{model::FullTrack, prelude::Id as _};
+use serde::{de::IgnoredAny, Deserialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    config::{Granularity, LyricsConfig},
+    lyrics::{Lyrics, LyricsContent},
+    text::truncate,
+    translation::Translator,
+};
+
+const LYRICS_URL: &str = "https://beautiful-lyrics.socalifornian.live/lyrics";
+const LRCLIB_URL: &str = "https://lrclib.net/api/get";
+const SPOTIFY_SEARCH_URL: &str = "https://api.spotify.com/v1/search";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LrclibTrack {
+    synced_lyrics: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    tracks: SearchTracks,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchTracks {
+    items: Vec<SearchTrack>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchTrack {
+    id: Option<String>,
+}
+
+/// Extracts the bare track id from a Spotify track URL, URI or id.
+pub fn normalize_track_id(input: &str) -> Option<String> {
+    let input = input.trim();
+
+    let id = if let Some(id) = input.strip_prefix("spotify:track:") {
+        id
+    } else if input.contains("open.spotify.com") {
+        let path = input.split(['?', '#']).next()?;
+        path.rsplit_once("/track/")?.1.trim_end_matches('/')
+    } else {
+        input
+    };
+
+    (id.len() == 22 && id.chars().all(|c| c.is_ascii_alphanumeric())).then(|| id.to_string())
+}
+
+/// Sent with lyrics requests unless `lyrics.headers` has its own.
+const USER_AGENT: &str = concat!("dyrics/", env!("CARGO_PKG_VERSION"));
+
+/// Builds the HTTP client lyrics requests are sent through. Compressed
+/// responses are decompressed on the way in.
+pub fn http_client(config: &LyricsConfig) -> eyre::Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(config.http_timeout)
+        .user_agent(USER_AGENT);
+    if let Some(ref proxy) = config.http_proxy {
+        builder = builder.proxy(proxy.to_proxy()?);
+    }
+    Ok(builder.build()?)
+}
+
+/// The ids to look a track's lyrics up by, in order. When Spotify relinks a
+/// track to a copy that is available in the user's market, the lyrics belong
+/// to the original it was linked from, so that id comes first and the id that
+/// is actually playing second.
+pub fn lyrics_track_ids(track: &FullTrack) -> Vec<&str> {
+    let mut ids = Vec::new();
+    if let Some(ref linked_from) = track.linked_from {
+        ids.push(linked_from.id.id());
+    }
+    if let Some(ref id) = track.id {
+        if !ids.contains(&id.id()) {
+            ids.push(id.id());
+        }
+    }
+    ids
+}
+
+/// The offset for `track`'s lyrics, from `lyrics.offsets_file` if it has one
+/// for any of its [`lyrics_track_ids`], or else `lyrics.offset`.
+pub fn lyrics_offset(config: &LyricsConfig, track: &FullTrack) -> f64 {
+    let Some(ref path) = config.offsets_file else {
+        return config.offset;
+    };
+    // read every time, so offsets can be tuned while dyrics runs
+    let offsets = match Figment::from(Toml::file_exact(path)).extract::<HashMap<String, f64>>() {
+        Ok(offsets) => offsets,
+        Err(err) => {
+            tracing::warn!("Failed to read {}: {}", path.display(), err);
+            return config.offset;
+        }
+    };
+
+    lyrics_track_ids(track)
+        .into_iter()
+        .find_map(|track_id| offsets.get(track_id).copied())
+        .unwrap_or(config.offset)
+}
+
+/// The key fallback lyrics are cached under. Relinked copies of a track share
+/// its ISRC, so they only get looked up once.
+fn fallback_key(track: &FullTrack) -> Option<String> {
+    track
+        .external_ids
+        .get("isrc")
+        .map(|isrc| format!("isrc:{}", isrc))
+        .or_else(|| track.id.as_ref().map(|id| id.id().to_string()))
+}
+
+pub struct LyricsProvider {
+    client: Client,
+    base_url: String,
+    search_url: String,
+    /// Only sent to the provider, not to LRCLIB
+    headers: HeaderMap,
+    /// Track ids the provider is known to have no lyrics for
+    missing: RwLock<HashSet<String>>,
+    /// Look tracks up by the other tracks with their ISRC when the provider
+    /// has no lyrics for them
+    isrc_fallback: bool,
+    /// The other tracks with an ISRC, by ISRC
+    isrc_tracks: RwLock<HashMap<String, Vec<String>>>,
+    /// Look tracks up on LRCLIB when the provider has no lyrics for them
+    lrclib_fallback: bool,
+    granularity: Granularity,
+    /// What LRCLIB came up with, see [`fallback_key`]
+    fallback: RwLock<HashMap<String, Option<Lyrics>>>,
+    translator: Option<Translator>,
+}
+
+impl LyricsProvider {
+    pub fn new(client: Client, config: &LyricsConfig) -> Self {
+        Self {
+            client,
+            base_url: LYRICS_URL.to_string(),
+            search_url: SPOTIFY_SEARCH_URL.to_string(),
+            headers: config.headers.header_map().clone(),
+            missing: RwLock::new(HashSet::new()),
+            isrc_fallback: config.isrc_fallback,
+            isrc_tracks: RwLock::new(HashMap::new()),
+            lrclib_fallback: config.lrclib_fallback,
+            granularity: config.preferred_granularity,
+            fallback: RwLock::new(HashMap::new()),
+            translator: None,
+        }
+    }
+
+    /// Translates the lyrics that are fetched.
+    pub fn with_translator(mut self, translator: Translator) -> Self {
+        self.translator = Some(translator);
+        self
+    }
+
+    /// Fetches the lyrics from `base_url` instead of the lyrics provider.
+    #[cfg(test)]
+    pub(crate) fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url;
+        self
+    }
+
+    fn lyrics_url(&self, track_id: &str) -> eyre::Result<String> {
+        let track_id = normalize_track_id(track_id)
+            .ok_or_else(|| eyre::eyre!("\"{}\" is not a spotify track", track_id))?;
+
+        Ok(format!("{}/{}", self.base_url, track_id))
+    }
+
+    pub async fn is_known_missing(&self, track_id: &str) -> bool {
+        self.missing.read().await.contains(track_id)
+    }
+
+    async fn mark_missing(&self, track_id: &str) {
+        self.missing.write().await.insert(track_id.to_string());
+    }
+
+    /// Cheaply checks whether lyrics exist for a track without downloading them.
+    /// An answer that says nothing about the lyrics, like during an outage, is
+    /// an error rather than `false`.
+    pub async fn has_lyrics(&self, track_id: &str, access_token: &str) -> eyre::Result<bool> {
+        if self.is_known_missing(track_id).await {
+            return Ok(false);
+        }
+
+        let response = self
+            .client
+            .head(self.lyrics_url(track_id)?)
+            .bearer_auth(access_token)
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => {
+                self.mark_missing(track_id).await;
+                Ok(false)
+            }
+            status if status.is_success() => Ok(true),
+            // says nothing about the lyrics, so nothing is remembered either
+            status => Err(eyre::eyre!(
+                "lyrics availability check failed with {}",
+                status
+            )),
+        }
+    }
+
+    /// Fetches the provider's response without interpreting it.
+    #[tracing::instrument(
+        name = "lyrics_request",
+        skip(self, access_token),
+        fields(url, status, size, elapsed_ms)
+    )]
+    pub async fn fetch_raw(
+        &self,
+        track_id: &str,
+        access_token: &str,
+    ) -> eyre::Result<(StatusCode, String)> {
+        let url = self.lyrics_url(track_id)?;
+        let span = tracing::Span::current();
+        span.record("url", url.as_str());
+
+        let started = Instant::now();
+        let response = self
+            .client
+            .get(&url)
+            .bearer_auth(access_token)
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        let status = response.status();
+        let bytes = response.bytes().await?;
+        let body = String::from_utf8_lossy(&bytes).into_owned();
+
+        span.record("status", status.as_u16());
+        span.record("size", body.len());
+        span.record("elapsed_ms", started.elapsed().as_millis() as u64);
+        // a missing track is answered with an error status too
+        if status.is_success() || matches!(status, StatusCode::NOT_FOUND | StatusCode::NO_CONTENT) {
+            tracing::debug!("lyrics request done");
+        } else {
+            tracing::warn!(body = %truncate(&body, 200), "lyrics request failed");
+        }
+
+        Ok((status, body))
+    }
+
+    /// Fetches the lyrics for `track` by the ids from [`lyrics_track_ids`],
+    /// falling back to the tracks with the same ISRC and then LRCLIB if the
+    /// provider has none and [`LyricsConfig::isrc_fallback`] or
+    /// [`LyricsConfig::lrclib_fallback`] is set. Lyrics that can't be
+    /// translated are still shown untranslated.
+    #[tracing::instrument(
+        skip_all,
+        fields(track_id = track.id.as_ref().map(|id| id.id()).unwrap_or_default())
+    )]
+    pub async fn fetch_lyrics(
+        &self,
+        track: &FullTrack,
+        access_token: &str,
+    ) -> eyre::Result<Option<Lyrics>> {
+        let mut lyrics = self.fetch_untranslated(track, access_token).await?;
+        if let (Some(ref mut lyrics), Some(ref translator)) = (&mut lyrics, &self.translator) {
+            if let Err(err) = translator.translate(lyrics).await {
+                tracing::warn!("Failed to translate the lyrics: {}", err);
+            }
+        }
+        Ok(lyrics)
+    }
+
+    async fn fetch_untranslated(
+        &self,
+        track: &FullTrack,
+        access_token: &str,
+    ) -> eyre::Result<Option<Lyrics>> {
+        let mut found = self
+            .fetch_preferred(&lyrics_track_ids(track), access_token)
+            .await?;
+        if found.is_none() && self.isrc_fallback {
+            found = self.fetch_by_isrc(track, access_token).await;
+        }
+        if found.is_none() && self.lrclib_fallback {
+            found = self.fetch_fallback(track).await;
+        }
+        Ok(match self.granularity {
+            Granularity::Line => found.map(Lyrics::into_lines),
+            _ => found,
+        })
+    }
+
+    /// The lyrics of the first of `track_ids` that has them by syllable, or
+    /// else of the first that has them at all. Lines are shown line by line
+    /// either way, so with [`Granularity::Line`] the first lyrics found do.
+    async fn fetch_preferred(
+        &self,
+        track_ids: &[&str],
+        access_token: &str,
+    ) -> eyre::Result<Option<Lyrics>> {
+        let mut found = None;
+        for track_id in track_ids {
+            let Some(lyrics) = self.fetch_by_id(track_id, access_token).await? else {
+                continue;
+            };
+            if self.granularity == Granularity::Line
+                || matches!(lyrics.content, LyricsContent::Syllable(_))
+            {
+                return Ok(Some(lyrics));
+            }
+            // the other id may still have them by syllable
+            found.get_or_insert(lyrics);
+        }
+        Ok(found)
+    }
+
+    /// Looks the track up by the other Spotify tracks with its ISRC. A failed
+    /// lookup only means there are no fallback lyrics, like with LRCLIB.
+    async fn fetch_by_isrc(&self, track: &FullTrack, access_token: &str) -> Option<Lyrics> {
+        let result = async {
+            let track_ids = self.isrc_track_ids(track, access_token).await?;
+            let track_ids = track_ids.iter().map(String::as_str).collect::<Vec<_>>();
+            self.fetch_preferred(&track_ids, access_token).await
+        }
+        .await;
+
+        result.unwrap_or_else(|err| {
+            tracing::warn!("Failed to look the lyrics up by ISRC: {:#}", err);
+            None
+        })
+    }
+
+    /// The other Spotify tracks with the same ISRC as `track`, remembered so
+    /// each ISRC is only searched for once.
+    async fn isrc_track_ids(
+        &self,
+        track: &FullTrack,
+        access_token: &str,
+    ) -> eyre::Result<Vec<String>> {
+        let Some(isrc) = track.external_ids.get("isrc") else {
+            return Ok(Vec::new());
+        };
+        if let Some(track_ids) = self.isrc_tracks.read().await.get(isrc) {
+            return Ok(track_ids.clone());
+        }
+
+        let query = format!("isrc:{}", isrc);
+        let response = self
+            .client
+            .get(&self.search_url)
+            .bearer_auth(access_token)
+            .query(&[("q", query.as_str()), ("type", "track")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<SearchResponse>()
+            .await?;
+
+        // the track itself was looked up already
+        let known = lyrics_track_ids(track);
+        let track_ids = response
+            .tracks
+            .items
+            .into_iter()
+            .filter_map(|item| item.id)
+            .filter(|id| !known.contains(&id.as_str()))
+            .collect::<Vec<_>>();
+        self.isrc_tracks
+            .write()
+            .await
+            .insert(isrc.clone(), track_ids.clone());
+
+        Ok(track_ids)
+    }
+
+    async fn fetch_by_id(
+        &self,
+        track_id: &str,
+        access_token: &str,
+    ) -> eyre::Result<Option<Lyrics>> {
+        if self.is_known_missing(track_id).await {
+            return Ok(None);
+        }
+
+        let (status, body) = self.fetch_raw(track_id, access_token).await?;
+
+        match status {
+            // only a clear answer is remembered, anything else may pass
+            StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => {
+                self.mark_missing(track_id).await;
+                Ok(None)
+            }
+            status if status.is_success() => match serde_json::from_str(&body) {
+                Ok(lyrics) => Ok(Some(lyrics)),
+                // other JSON is how the provider says it has no lyrics
+                Err(_) if serde_json::from_str::<IgnoredAny>(&body).is_ok() => Ok(None),
+                Err(err) => Err(eyre::eyre!(
+                    "the lyrics provider responded with something that isn't JSON ({}): {}",
+                    err,
+                    truncate(&body, 100)
+                )),
+            },
+            status => Err(eyre::eyre!("lyrics request failed with {}", status)),
+        }
+    }
+
+    /// Looks the track up on LRCLIB by its name, artist, album and duration.
+    /// LRCLIB being unavailable only means there are no fallback lyrics.
+    async fn fetch_fallback(&self, track: &FullTrack) -> Option<Lyrics> {
+        let key = fallback_key(track)?;
+        if let Some(lyrics) = self.fallback.read().await.get(&key) {
+            return lyrics.clone();
+        }
+
+        let lyrics = match self.request_fallback(track).await {
+            Ok(lyrics) => lyrics,
+            Err(err) => {
+                // not remembered, it may well work for the next track
+                tracing::warn!("Failed to look the lyrics up on LRCLIB: {:#}", err);
+                return None;
+            }
+        };
+        self.fallback.write().await.insert(key, lyrics.clone());
+
+        lyrics
+    }
+
+    async fn request_fallback(&self, track: &FullTrack) -> eyre::Result<Option<Lyrics>> {
+        let duration = track.duration.to_std().unwrap_or_default();
+        let artist = track
+            .artists
+            .first()
+            .map(|artist| artist.name.as_str())
+            .unwrap_or_default();
+        let response = self
+            .client
+            .get(LRCLIB_URL)
+            .query(&[
+                ("track_name", track.name.as_str()),
+                ("artist_name", artist),
+                ("album_name", track.album.name.as_str()),
+                ("duration", &duration.as_secs().to_string()),
+            ])
+            .send()
+            .await?;
+
+        Ok(match response.status() {
+            StatusCode::NOT_FOUND => None,
+            _ => response
+                .error_for_status()?
+                .json::<LrclibTrack>()
+                .await?
+                .synced_lyrics
+                .and_then(|lrc| Lyrics::from_lrc(&lrc, duration)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write as _, time::Duration};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+    use crate::test_util::{response, serve, serve_nothing, serve_with, track, LINE_LYRICS};
+
+    /// Answers every request with `body`, gzip compressed.
+    async fn serve_gzip(body: &str) -> String {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let gzip = move |_: &str| {
+            Some(response(
+                "200 OK",
+                "content-encoding: gzip\r\n",
+                &compressed,
+            ))
+        };
+        serve_with(gzip).await.0
+    }
+
+    const TRACK_ID: &str = "4uLU6hMCjMI75M1A2tKUQC";
+
+    fn provider(base_url: String) -> LyricsProvider {
+        provider_with(base_url, &LyricsConfig::default())
+    }
+
+    fn provider_with(base_url: String, config: &LyricsConfig) -> LyricsProvider {
+        LyricsProvider::new(http_client(config).unwrap(), config)
+            .with_base_url(format!("{}/lyrics", base_url))
+    }
+
+    #[tokio::test]
+    async fn remembers_tracks_without_lyrics() {
+        let (url, requests) = serve("404 Not Found", "", "").await;
+        let provider = provider(url);
+
+        assert!(provider
+            .fetch_by_id(TRACK_ID, "token")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(provider.is_known_missing(TRACK_ID).await);
+        assert!(provider
+            .fetch_by_id(TRACK_ID, "token")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!provider.has_lyrics(TRACK_ID, "token").await.unwrap());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn availability_check_remembers_missing_lyrics() {
+        let (url, requests) = serve("204 No Content", "", "").await;
+        let provider = provider(url);
+
+        assert!(!provider.has_lyrics(TRACK_ID, "token").await.unwrap());
+        assert!(provider
+            .fetch_by_id(TRACK_ID, "token")
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_not_remembered() {
+        let (url, requests) = serve("500 Internal Server Error", "", "").await;
+        let provider = provider(url);
+
+        assert!(provider.has_lyrics(TRACK_ID, "token").await.is_err());
+        assert!(provider.fetch_by_id(TRACK_ID, "token").await.is_err());
+        assert!(provider.fetch_by_id(TRACK_ID, "token").await.is_err());
+        assert!(!provider.is_known_missing(TRACK_ID).await);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_that_arent_json() {
+        let (url, _) = serve("200 OK", "", "<html>busy</html>").await;
+        let provider = provider(url);
+
+        assert!(provider.fetch_by_id(TRACK_ID, "token").await.is_err());
+        assert!(!provider.is_known_missing(TRACK_ID).await);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_a_provider_that_hangs() {
+        let config = LyricsConfig {
+            http_timeout: Duration::from_millis(100),
+            ..LyricsConfig::default()
+        };
+        let provider = provider_with(serve_nothing().await, &config);
+
+        let err = provider.fetch_by_id(TRACK_ID, "token").await.unwrap_err();
+
+        let timed_out = err.chain().any(|cause| {
+            cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(reqwest::Error::is_timeout)
+        });
+        assert!(timed_out, "{:#}", err);
+        assert!(!provider.is_known_missing(TRACK_ID).await);
+    }
+
+    #[tokio::test]
+    async fn sends_the_configured_headers() {
+        let (url, requests) = serve("404 Not Found", "", "").await;
+        let headers = HashMap::from([("X-Api-Key".to_string(), "secret".to_string())]);
+        let config = LyricsConfig {
+            headers: headers.try_into().unwrap(),
+            ..LyricsConfig::default()
+        };
+        let provider = provider_with(url, &config);
+
+        provider.fetch_by_id(TRACK_ID, "token").await.unwrap();
+
+        let request = requests.lock().unwrap()[0].to_lowercase();
+        assert!(request.contains("\r\nx-api-key: secret\r\n"), "{}", request);
+        assert!(request.contains(&format!("\r\nuser-agent: {}\r\n", USER_AGENT)));
+        assert!(!format!("{:?}", config).contains("secret"));
+    }
+
+    #[test]
+    fn offsets_file_takes_precedence_over_the_global_offset() {
+        let path = std::env::temp_dir().join(format!("dyrics-offsets-{}.toml", std::process::id()));
+        std::fs::write(&path, format!("{} = -0.5\n", TRACK_ID)).unwrap();
+        let config = LyricsConfig {
+            offset: 0.25,
+            offsets_file: Some(path.clone()),
+            ..LyricsConfig::default()
+        };
+
+        let overridden = lyrics_offset(&config, &track(TRACK_ID));
+        let global = lyrics_offset(&config, &track("0VjIjW4GlUZAMYd2vXMi3b"));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(overridden, -0.5);
+        assert_eq!(global, 0.25);
+    }
+
+    #[tokio::test]
+    async fn decompresses_gzip_responses() {
+        let provider = provider(serve_gzip(LINE_LYRICS).await);
+
+        let lyrics = provider.fetch_by_id(TRACK_ID, "token").await.unwrap();
+
+        let Some(Lyrics {
+            content: LyricsContent::Line(lines),
+            ..
+        }) = lyrics
+        else {
+            panic!("expected lyrics synced by line");
+        };
+        assert_eq!(lines[0].text, "a line");
+    }
+
+    #[tokio::test]
+    async fn rejects_gzip_bodies_that_arent_json() {
+        let provider = provider(serve_gzip("<html>busy</html>").await);
+
+        let err = provider.fetch_by_id(TRACK_ID, "token").await.unwrap_err();
+
+        assert!(
+            err.to_string()
+                .starts_with("the lyrics provider responded with something that isn't JSON"),
+            "{}",
+            err
+        );
+    }
+
+    const RELINKED_ID: &str = "0VjIjW4GlUZAMYd2vXMi3b";
+
+    const SYLLABLE_LYRICS: &str = r#"{"StartTime":0.0,"EndTime":1.0,"Type":"Syllable","Content":[
+        {"Type":"Vocal","OppositeAligned":false,"Lead":{"StartTime":0.0,"EndTime":1.0,"Syllables":[
+            {"Text":"a","IsPartOfWord":false,"StartTime":0.0,"EndTime":0.5},
+            {"Text":"line","IsPartOfWord":false,"StartTime":0.5,"EndTime":1.0}
+        ]}}
+    ]}"#;
+
+    /// Answers requests for the track ids in `lyrics` with their lyrics and
+    /// any other with a 404.
+    async fn serve_by_id(lyrics: &'static [(&'static str, &'static str)]) -> String {
+        let by_id = |request: &str| {
+            let found = lyrics
+                .iter()
+                .find(|(id, _)| request.starts_with(&format!("GET /lyrics/{} ", id)));
+            Some(match found {
+                Some((_, body)) => response("200 OK", "", body.as_bytes()),
+                None => response("404 Not Found", "", b""),
+            })
+        };
+        serve_with(by_id).await.0
+    }
+
+    /// A track relinked from [`TRACK_ID`], which is looked up first.
+    fn relinked_track() -> FullTrack {
+        let mut track = track(RELINKED_ID);
+        track.linked_from = serde_json::from_value(serde_json::json!({
+            "external_urls": {},
+            "href": "",
+            "id": TRACK_ID
+        }))
+        .unwrap();
+        track
+    }
+
+    async fn fetch_with(
+        granularity: Granularity,
+        lyrics: &'static [(&'static str, &'static str)],
+    ) -> LyricsContent {
+        let config = LyricsConfig {
+            preferred_granularity: granularity,
+            ..LyricsConfig::default()
+        };
+        let provider = provider_with(serve_by_id(lyrics).await, &config);
+
+        provider
+            .fetch_untranslated(&relinked_track(), "token")
+            .await
+            .unwrap()
+            .unwrap()
+            .content
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_tracks_with_the_same_isrc() {
+        const OTHER_ID: &str = "1BxfuPKGuaTgP7aM0Bbdwr";
+        let (search_url, searches) = serve(
+            "200 OK",
+            "",
+            r#"{"tracks":{"items":[{"id":"4uLU6hMCjMI75M1A2tKUQC"},{"id":"1BxfuPKGuaTgP7aM0Bbdwr"}]}}"#,
+        )
+        .await;
+        let config = LyricsConfig {
+            isrc_fallback: true,
+            ..LyricsConfig::default()
+        };
+        let provider = LyricsProvider {
+            search_url: format!("{}/search", search_url),
+            ..provider_with(serve_by_id(&[(OTHER_ID, LINE_LYRICS)]).await, &config)
+        };
+        // nothing but the ISRC is the same as the track the lyrics are under
+        let mut track = track(TRACK_ID);
+        track.name = "Song (Remastered)".to_string();
+        track
+            .external_ids
+            .insert("isrc".to_string(), "USUM71703861".to_string());
+
+        for _ in 0..2 {
+            let lyrics = provider.fetch_untranslated(&track, "token").await.unwrap();
+            assert!(matches!(lyrics.unwrap().content, LyricsContent::Line(_)));
+        }
+
+        let searches = searches.lock().unwrap();
+        assert_eq!(searches.len(), 1);
+        assert!(searches[0].starts_with("GET /search?q=isrc%3AUSUM71703861&type=track "));
+    }
+
+    #[tokio::test]
+    async fn auto_keeps_looking_for_syllables() {
+        let both = &[(TRACK_ID, LINE_LYRICS), (RELINKED_ID, SYLLABLE_LYRICS)];
+        let lines_only = &[(TRACK_ID, LINE_LYRICS)];
+
+        for granularity in [Granularity::Auto, Granularity::Syllable] {
+            let content = fetch_with(granularity, both).await;
+            assert!(matches!(content, LyricsContent::Syllable(_)));
+
+            // settles for lines if that's all there is
+            let content = fetch_with(granularity, lines_only).await;
+            assert!(matches!(content, LyricsContent::Line(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn line_shows_syllables_line_by_line() {
+        let syllables_only = &[(RELINKED_ID, SYLLABLE_LYRICS)];
+
+        let content = fetch_with(Granularity::Line, syllables_only).await;
+
+        let LyricsContent::Line(lines) = content else {
+            panic!("expected lyrics synced by line");
+        };
+        assert_eq!(lines[0].text, "a line");
+    }
+}
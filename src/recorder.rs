@@ -0,0 +1,118 @@
+//! An opt-in record of a session, for bug reports about the timing. Every
+//! event is appended to the file as a line of JSON, with enough in it to run
+//! the lyrics back through the scheduling without Spotify.
+
+use std::{
+    fs::File,
+    io::Write as _,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use rspotify::model::FullTrack;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationMilliSeconds};
+
+use crate::{
+    config::RecorderConfig, error::DyricsError, lyrics::Lyrics, schedule::ScheduledUpdate,
+};
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A new track, with the lyrics as they were fetched
+    Track {
+        generation: u64,
+        track: Box<FullTrack>,
+        #[serde(default)]
+        context: Option<String>,
+        lyrics: Option<Lyrics>,
+        #[serde_as(as = "DurationMilliSeconds<u64>")]
+        #[serde(rename = "position_ms")]
+        position: Duration,
+    },
+    /// The position as the source reported it
+    Position {
+        #[serde_as(as = "DurationMilliSeconds<u64>")]
+        #[serde(rename = "position_ms")]
+        position: Duration,
+    },
+    /// Nothing is playing anymore
+    Stopped,
+    /// The updates planned after a track change or seek
+    Schedule {
+        generation: u64,
+        #[serde_as(as = "DurationMilliSeconds<u64>")]
+        #[serde(rename = "position_ms")]
+        position: Duration,
+        updates: Vec<ScheduledUpdate>,
+    },
+    /// A status that went out to a sink
+    Status {
+        sink: String,
+        text: String,
+        emoji: String,
+    },
+    Cleared {
+        sink: String,
+    },
+    Error {
+        context: String,
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Appends events to the session file. Cheap to clone, and does nothing if
+/// there is no `[recorder]` section.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl Recorder {
+    pub fn new(config: Option<&RecorderConfig>) -> Result<Self, DyricsError> {
+        let file = match config {
+            Some(config) => Some(
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(&config.path)?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            file: file.map(|file| Arc::new(Mutex::new(file))),
+        })
+    }
+
+    /// Records the event made by `event`, which is only called when recording.
+    pub fn record(&self, event: impl FnOnce() -> Event) {
+        let Some(ref file) = self.file else {
+            return;
+        };
+
+        let record = Record {
+            at: Utc::now(),
+            event: event(),
+        };
+        let result = serde_json::to_string(&record)
+            .map_err(std::io::Error::from)
+            .and_then(|line| {
+                let mut file = file.lock().unwrap_or_else(|err| err.into_inner());
+                writeln!(file, "{}", line)
+            });
+        // a broken recording shouldn't take the session down with it
+        if let Err(err) = result {
+            tracing::warn!("Failed to record the session: {}", err);
+        }
+    }
+}
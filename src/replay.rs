@@ -0,0 +1,181 @@
+//! Plays a session recorded by the [`Recorder`](crate::recorder::Recorder)
+//! back through the status loop, printing what would be sent instead of
+//! sending it. The loops keep time by the clock, so a replay takes as long
+//! as the session did.
+
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use crate::{
+    config::Config,
+    error::DyricsError,
+    playback::{step_loop, CurrentPlayback, SharedPlayback},
+    recorder::{Event, Record, Recorder},
+    shutdown::Shutdown,
+    sink::{RequestWindow, StatusSink},
+    status::status_loop,
+};
+
+/// Prints updates instead of sending them, skipping them like the Discord
+/// sink would once its rate limit is used up.
+struct ReplaySink {
+    started: Instant,
+    requests: RequestWindow,
+    last_sent: Option<String>,
+}
+
+impl ReplaySink {
+    fn new(config: &Config, started: Instant) -> Self {
+        Self {
+            started,
+            requests: RequestWindow::new(
+                config.discord.rate_limit,
+                config.discord.rate_limit_window,
+            ),
+            last_sent: None,
+        }
+    }
+}
+
+#[async_trait]
+impl StatusSink for ReplaySink {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    async fn send_update(&mut self, text: &str, _emoji: &str) -> Result<bool, DyricsError> {
+        if self.last_sent.as_deref() == Some(text) || !self.requests.has_capacity() {
+            return Ok(false);
+        }
+
+        self.requests.record(Instant::now());
+        self.last_sent = Some(text.to_string());
+        print_line(self.started.elapsed(), "replayed", text);
+        Ok(true)
+    }
+
+    async fn clear_status(&mut self) -> Result<(), DyricsError> {
+        if self.last_sent.take().is_some() {
+            self.requests.record(Instant::now());
+            print_line(self.started.elapsed(), "replayed", "(cleared)");
+        }
+        Ok(())
+    }
+}
+
+fn print_line(at: Duration, kind: &str, text: &str) {
+    println!("{:>9.3}s  {:<8}  {}", at.as_secs_f64(), kind, text);
+}
+
+/// Reads the events of a recorded session, in the order they happened.
+pub fn read_session(path: &Path) -> eyre::Result<Vec<Record>> {
+    let contents = std::fs::read_to_string(path)?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|err| {
+                eyre::eyre!(
+                    "line {} of {} is no event: {}",
+                    index + 1,
+                    path.display(),
+                    err
+                )
+            })
+        })
+        .collect()
+}
+
+/// Feeds the playback of a recorded session to the status loop as it was
+/// recorded, printing the statuses that were sent back then next to the
+/// ones that would be sent now.
+pub async fn replay(config: &Config, path: &Path) -> eyre::Result<()> {
+    let records = read_session(path)?;
+    let Some(first) = records.first() else {
+        eyre::bail!("{} has no events to replay", path.display());
+    };
+    let recorded_start = first.at;
+
+    let started = Instant::now();
+    let current_playback: SharedPlayback = Arc::new(RwLock::new(None));
+    let (stop, shutdown) = Shutdown::channel();
+    let stepping = config.spotify.use_stepping.then(|| {
+        tokio::spawn(step_loop(
+            current_playback.clone(),
+            config.spotify.stale_after,
+            shutdown.clone(),
+        ))
+    });
+
+    let feed = async {
+        for record in records {
+            let at = (record.at - recorded_start).to_std().unwrap_or_default();
+            tokio::time::sleep(at.saturating_sub(started.elapsed())).await;
+
+            match record.event {
+                Event::Track {
+                    generation,
+                    track,
+                    context,
+                    lyrics,
+                    position,
+                } => {
+                    print_line(at, "track", &track.name);
+                    *current_playback.write().await = Some(CurrentPlayback {
+                        lyrics,
+                        duration: track.duration.to_std().unwrap_or_default(),
+                        track: *track,
+                        position,
+                        context,
+                        generation,
+                        synced_at: Instant::now(),
+                    });
+                }
+                Event::Position { position } => {
+                    if let Some(ref mut playback) = *current_playback.write().await {
+                        playback.position = position;
+                        playback.synced_at = Instant::now();
+                    }
+                }
+                Event::Stopped => *current_playback.write().await = None,
+                Event::Status { text, .. } => print_line(at, "recorded", &text),
+                Event::Cleared { .. } => print_line(at, "recorded", "(cleared)"),
+                Event::Error { context, message } => {
+                    print_line(at, "error", &format!("{}: {}", context, message))
+                }
+                Event::Schedule { .. } => {}
+            }
+        }
+        // long enough for the last line to play out
+        tokio::time::sleep(config.discord.clear_grace + config.display.fade_out).await;
+    };
+
+    let status = status_loop(
+        current_playback.clone(),
+        vec![Box::new(ReplaySink::new(config, started))],
+        config,
+        Recorder::default(),
+        shutdown,
+    );
+    let stopping = async {
+        tokio::select! {
+            _ = feed => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+        let _ = stop.send(true);
+        Ok(())
+    };
+    tokio::try_join!(status, stopping)?;
+    if let Some(stepping) = stepping {
+        stepping.await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,744 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationMilliSeconds};
+
+use crate::{
+    config::{BatchSeparator, Config, LongLineMode, SinkKind},
+    lyrics::TimedLine,
+    template::render,
+    text::{scroll_frames, split_at_words, truncate, MAX_STATUS_LEN},
+};
+
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledUpdate {
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    #[serde(rename = "display_time_ms")]
+    pub display_time: Duration,
+    pub text: String,
+    /// When the lines behind `text` are over
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    #[serde(rename = "end_time_ms")]
+    pub end_time: Duration,
+}
+
+/// Lines that get shown together in one update.
+struct Batch {
+    display_time: Duration,
+    text: String,
+    /// When the text stops being relevant
+    end_time: Duration,
+    word_times: Vec<Duration>,
+    /// Shown after `text` if there is time, its translation or romanization
+    /// if [`ScheduleOptions::alternate_romanized`] is set
+    alternate: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduleOptions {
+    /// Smallest gap between two updates that keeps us within the rate limit
+    pub min_interval: Duration,
+    pub long_lines: LongLineMode,
+    /// Longest status, at most [`MAX_STATUS_LEN`]
+    pub max_status_len: usize,
+    pub scroll_width: usize,
+    pub scroll_step: usize,
+    /// Gaps at least this long show the following line as soon as they start
+    pub lookahead_gap: Option<Duration>,
+    /// Gaps between lines at least this long show the track title
+    pub title_gap: Option<Duration>,
+    /// Most lines batched into one update, older ones get dropped
+    pub max_batch_lines: Option<usize>,
+    pub batch_separator: BatchSeparator,
+    /// Batches of lines no longer than this get the short separator
+    pub short_line_len: usize,
+    /// Lines shorter than this are merged into the line after them
+    pub min_line_duration: Option<Duration>,
+    /// Template the name of a new section is shown with before it starts
+    pub section_format: Option<String>,
+    /// Follow lines up with their romanization when there is time
+    pub alternate_romanized: bool,
+    /// Only every this many lines are shown, the others are dropped
+    pub line_stride: usize,
+    /// Show lines and their translation as "line / translation" when that
+    /// fits, instead of one after the other
+    pub side_by_side_translation: bool,
+    /// Leave out lines the previous update ended with
+    pub trim_overlap: bool,
+}
+
+impl ScheduleOptions {
+    pub fn from_config(config: &Config) -> Self {
+        let min_interval = config
+            .sinks
+            .iter()
+            .map(|kind| match kind {
+                SinkKind::Discord => {
+                    config.discord.rate_limit_window / config.discord.rate_limit.max(1) as u32
+                }
+                SinkKind::Notification => config.notification.min_interval,
+                _ => Duration::ZERO,
+            })
+            .max()
+            .unwrap_or_default();
+
+        let max_status_len = config.display.max_status_len.clamp(1, MAX_STATUS_LEN);
+        Self {
+            min_interval,
+            long_lines: config.display.long_lines,
+            max_status_len,
+            scroll_width: config.display.scroll_width.min(max_status_len),
+            scroll_step: config.display.scroll_step,
+            lookahead_gap: config
+                .display
+                .lookahead_during_gap
+                .then_some(config.display.lookahead_gap),
+            title_gap: config
+                .display
+                .title_during_gap
+                .then_some(config.display.title_gap),
+            max_batch_lines: config.display.max_batch_lines,
+            batch_separator: config.display.batch_separator,
+            short_line_len: config.display.short_line_len,
+            min_line_duration: config.display.min_line_duration,
+            section_format: config
+                .display
+                .section_labels
+                .then(|| config.display.section_format.clone()),
+            alternate_romanized: config.display.alternate_romanized,
+            line_stride: config.display.line_stride,
+            side_by_side_translation: config
+                .translation
+                .as_ref()
+                .is_some_and(|translation| translation.side_by_side),
+            trim_overlap: config.display.trim_overlap,
+        }
+    }
+}
+
+/// Merges lines shorter than `min_duration` into the line after them, so
+/// they don't flash by in an update of their own. A short last line is kept.
+pub fn merge_short_lines(lines: &[TimedLine], min_duration: Duration) -> Vec<TimedLine> {
+    let mut merged = Vec::with_capacity(lines.len());
+    let mut pending: Option<TimedLine> = None;
+
+    for (index, line) in lines.iter().enumerate() {
+        let line = match pending.take() {
+            Some(short) => {
+                let mut word_starts = short.word_timings();
+                word_starts.extend(line.word_timings());
+                TimedLine {
+                    text: format!("{}. {}", short.text, line.text),
+                    start_time: short.start_time,
+                    end_time: short.end_time.max(line.end_time),
+                    word_starts,
+                    section: short.section,
+                    line_type: short.line_type,
+                    opposite_aligned: short.opposite_aligned,
+                    romanized: merge_variants(
+                        (&short.text, short.romanized),
+                        (&line.text, &line.romanized),
+                    ),
+                    translated: merge_variants(
+                        (&short.text, short.translated),
+                        (&line.text, &line.translated),
+                    ),
+                }
+            }
+            None => line.clone(),
+        };
+
+        let is_last = index + 1 == lines.len();
+        if !is_last && line.end_time.saturating_sub(line.start_time) < min_duration {
+            pending = Some(line);
+        } else {
+            merged.push(line);
+        }
+    }
+
+    merged
+}
+
+/// Merges the romanization or translation of two lines, keeping the
+/// original text of the line that has none.
+fn merge_variants(
+    (short_text, short): (&str, Option<String>),
+    (text, variant): (&str, &Option<String>),
+) -> Option<String> {
+    match (short, variant) {
+        (None, None) => None,
+        (short, variant) => Some(format!(
+            "{}. {}",
+            short.as_deref().unwrap_or(short_text),
+            variant.as_deref().unwrap_or(text)
+        )),
+    }
+}
+
+/// The separator between the lines of a batch, the short one of `kind` if
+/// every line is at most `short_line_len` characters long.
+pub fn batch_separator(
+    kind: BatchSeparator,
+    lines: &[&str],
+    short_line_len: usize,
+) -> &'static str {
+    let short = lines
+        .iter()
+        .all(|line| line.chars().count() <= short_line_len);
+    match kind {
+        BatchSeparator::Slash if short => " / ",
+        BatchSeparator::Dot if short => " · ",
+        _ => ". ",
+    }
+}
+
+/// Splits a batch back into its lines, along with where each starts.
+fn split_batch(text: &str, kind: BatchSeparator) -> Vec<(usize, &str)> {
+    let separators: &[&str] = match kind {
+        BatchSeparator::Period => &[". "],
+        BatchSeparator::Slash => &[". ", " / "],
+        BatchSeparator::Dot => &[". ", " · "],
+    };
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while let Some((at, separator)) = separators
+        .iter()
+        .filter_map(|separator| Some((start + text[start..].find(separator)?, separator)))
+        .min_by_key(|&(at, _)| at)
+    {
+        lines.push((start, &text[start..at]));
+        start = at + separator.len();
+    }
+    lines.push((start, &text[start..]));
+    lines
+}
+
+/// Leaves out the lines at the start of `text` that `previous` ended with,
+/// e.g. "b. c" after "a. b" becomes "c". Returns `None` if they don't
+/// overlap, or if nothing would be left.
+pub fn trim_overlap(previous: &str, text: &str, separator: BatchSeparator) -> Option<String> {
+    let previous = split_batch(previous.trim_start_matches('…'), separator);
+    let text = text.trim_start_matches('…');
+    let lines = split_batch(text, separator);
+
+    // the longest overlap wins
+    let overlap = (1..lines.len().min(previous.len() + 1))
+        .rev()
+        .find(|&count| {
+            previous[previous.len() - count..]
+                .iter()
+                .map(|&(_, line)| line)
+                .eq(lines[..count].iter().map(|&(_, line)| line))
+        })?;
+    Some(text[lines[overlap].0..].to_string())
+}
+
+/// Plans the updates needed to show `lines` from `position` onwards. Lines
+/// starting before the rate limit allows another update get batched into the
+/// previous one. Gaps get the name of the section that follows if
+/// [`ScheduleOptions::section_format`] is set, or else `title` if they are
+/// longer than [`ScheduleOptions::title_gap`].
+///
+/// When rebuilding mid-song, `last_displayed` is the display time of the last
+/// update that was shown. Lines that could already be shown by then are left
+/// out so they aren't sent again.
+pub fn build_schedule(
+    lines: &[TimedLine],
+    title: Option<&str>,
+    position: Duration,
+    last_displayed: Option<Duration>,
+    options: &ScheduleOptions,
+) -> Vec<ScheduledUpdate> {
+    build_schedule_from(lines, title, position, position, last_displayed, options)
+}
+
+/// Like [`build_schedule`], but the first update is held back until
+/// `not_before`, e.g. because a slot was already used at `position`.
+pub fn build_schedule_from(
+    lines: &[TimedLine],
+    title: Option<&str>,
+    position: Duration,
+    not_before: Duration,
+    last_displayed: Option<Duration>,
+    options: &ScheduleOptions,
+) -> Vec<ScheduledUpdate> {
+    let merged;
+    let lines = match options.min_line_duration {
+        Some(min_duration) => {
+            merged = merge_short_lines(lines, min_duration);
+            &merged[..]
+        }
+        None => lines,
+    };
+
+    // (line, earliest time it may be shown)
+    let mut gap_start = Duration::ZERO;
+    let mut upcoming = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        // an overlapping repeat of a line that is still showing adds nothing
+        let repeated = lines[..index].iter().any(|previous| {
+            previous.text == line.text
+                && previous.start_time <= line.start_time
+                && line.end_time <= previous.end_time
+        });
+        if repeated {
+            continue;
+        }
+
+        let earliest = match options.lookahead_gap {
+            Some(min_gap) if line.start_time.saturating_sub(gap_start) >= min_gap => gap_start,
+            _ => line.start_time,
+        };
+        gap_start = gap_start.max(line.end_time);
+
+        // only every nth line of the song gets shown, and always the last one
+        let stride = options.line_stride.max(1);
+        if (index + 1) % stride != 0 && index + 1 != lines.len() {
+            continue;
+        }
+
+        let shown = last_displayed.is_some_and(|last_displayed| earliest <= last_displayed);
+        if line.end_time > position && !shown {
+            upcoming.push((line, earliest));
+        }
+    }
+
+    let mut batches = Vec::new();
+    let mut next_available = not_before.max(position);
+    let mut index = 0;
+    while index < upcoming.len() {
+        let display_time = upcoming[index].1.max(next_available);
+        let slot_end = display_time + options.min_interval;
+
+        let mut end = index + 1;
+        while end < upcoming.len() && upcoming[end].1 < slot_end {
+            end += 1;
+        }
+
+        let mut lines = &upcoming[index..end];
+        let mut prefix = "";
+        if let Some(max_lines) = options.max_batch_lines {
+            if lines.len() > max_lines.max(1) {
+                // the newest lines stay relevant the longest
+                lines = &lines[lines.len() - max_lines.max(1)..];
+                prefix = "…";
+            }
+        }
+        // the original lines decide, so a romanization is joined the same way
+        let separator = batch_separator(
+            options.batch_separator,
+            &lines
+                .iter()
+                .map(|(line, _)| line.text.as_str())
+                .collect::<Vec<_>>(),
+            options.short_line_len,
+        );
+        let join = |text_of: fn(&TimedLine) -> &str| {
+            // an empty `blank_line` has nothing to add next to other lines
+            let texts = lines
+                .iter()
+                .map(|(line, _)| text_of(line))
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>();
+            format!("{}{}", prefix, texts.join(separator))
+        };
+        let text = join(|line| &line.text);
+        let translated = lines
+            .iter()
+            .any(|(line, _)| line.translated.is_some())
+            .then(|| join(|line| line.translated.as_deref().unwrap_or(&line.text)))
+            .filter(|translated| *translated != text);
+        let (text, alternate) = match translated {
+            Some(translated) => {
+                let side_by_side = format!("{} / {}", text, translated);
+                if options.side_by_side_translation
+                    && side_by_side.chars().count() <= options.max_status_len
+                {
+                    (side_by_side, None)
+                } else {
+                    (text, Some(translated))
+                }
+            }
+            None => {
+                let alternate = lines
+                    .iter()
+                    .any(|(line, _)| line.romanized.is_some())
+                    .then(|| join(|line| line.romanized.as_deref().unwrap_or(&line.text)))
+                    .filter(|alternate| options.alternate_romanized && *alternate != text);
+                (text, alternate)
+            }
+        };
+
+        let batch = Batch {
+            display_time,
+            text,
+            alternate,
+            // with overlapping lines the last one to start isn't always the last to end
+            end_time: lines
+                .iter()
+                .map(|(line, _)| line.end_time)
+                .max()
+                .unwrap_or(display_time),
+            word_times: lines
+                .iter()
+                .enumerate()
+                .flat_map(|(index, (line, _))| {
+                    let word_times = line.word_timings();
+                    // a separator like " / " is a word of its own, which
+                    // comes up with the line after it
+                    let separator_time = (index > 0 && separator.starts_with(' '))
+                        .then(|| word_times.first().copied().unwrap_or(line.start_time));
+                    separator_time.into_iter().chain(word_times)
+                })
+                .collect(),
+        };
+        next_available = slot_end;
+
+        // fill the gap before the next line with the name of the section it
+        // starts, or failing that the title during a long gap, as long as
+        // that doesn't hold up the line
+        let filler = upcoming.get(end).and_then(|&(next, next_start)| {
+            let new_section = next
+                .section
+                .as_deref()
+                .filter(|&section| Some(section) != upcoming[end - 1].0.section.as_deref());
+            let text = match (new_section, &options.section_format) {
+                (Some(section), Some(format)) => render(format, &[("section", section)]),
+                _ => match (title, options.title_gap) {
+                    (Some(title), Some(min_gap))
+                        if next_start.saturating_sub(batch.end_time) >= min_gap =>
+                    {
+                        title.to_string()
+                    }
+                    _ => return None,
+                },
+            };
+
+            let filler_time = batch.end_time.max(next_available);
+            (filler_time + options.min_interval <= next_start).then(|| Batch {
+                display_time: filler_time,
+                word_times: vec![filler_time; text.split_whitespace().count()],
+                text,
+                end_time: next_start,
+                alternate: None,
+            })
+        });
+
+        batches.push(batch);
+        if let Some(filler) = filler {
+            next_available = filler.display_time + options.min_interval;
+            batches.push(filler);
+        }
+        index = end;
+    }
+
+    if options.trim_overlap {
+        let texts = batches
+            .iter()
+            .map(|batch| batch.text.clone())
+            .collect::<Vec<_>>();
+        for (batch, previous) in batches.iter_mut().skip(1).zip(&texts) {
+            if let Some(trimmed) = trim_overlap(previous, &batch.text, options.batch_separator) {
+                // the trimmed words were at the front
+                let removed =
+                    batch.text.split_whitespace().count() - trimmed.split_whitespace().count();
+                batch
+                    .word_times
+                    .drain(..removed.min(batch.word_times.len()));
+                batch.text = trimmed;
+            }
+        }
+    }
+
+    let mut schedule = Vec::new();
+    for (index, batch) in batches.iter().enumerate() {
+        let display_time = batch.display_time;
+        let window_end = batches
+            .get(index + 1)
+            .map(|next| next.display_time)
+            .unwrap_or(batch.end_time)
+            .max(display_time);
+        let too_long = batch.text.chars().count() > options.scroll_width;
+
+        match options.long_lines {
+            LongLineMode::Scroll if too_long => {
+                let window = window_end.saturating_sub(display_time);
+                let slots = if options.min_interval.is_zero() {
+                    usize::MAX
+                } else {
+                    (window.as_secs_f64() / options.min_interval.as_secs_f64()) as usize
+                };
+
+                let frames = scroll_frames(
+                    &batch.text,
+                    options.scroll_width,
+                    options.scroll_step,
+                    slots,
+                );
+                let spacing = window / frames.len() as u32;
+                for (frame_index, frame) in frames.into_iter().enumerate() {
+                    schedule.push(ScheduledUpdate {
+                        display_time: display_time + spacing * frame_index as u32,
+                        text: frame,
+                        end_time: batch.end_time,
+                    });
+                }
+            }
+            LongLineMode::Split if too_long => {
+                let chunks = split_at_words(&batch.text, &batch.word_times, options.scroll_width);
+                let mut next_chunk = display_time;
+                for (chunk_index, (word_time, chunk)) in chunks.into_iter().enumerate() {
+                    let chunk_time = word_time.max(next_chunk);
+                    if chunk_index > 0 && chunk_time >= window_end {
+                        // out of slots, hint that the line went on
+                        if let Some(last) = schedule.last_mut() {
+                            last.text =
+                                truncate(&format!("{}…", last.text), options.max_status_len);
+                        }
+                        break;
+                    }
+
+                    schedule.push(ScheduledUpdate {
+                        display_time: chunk_time,
+                        text: chunk,
+                        end_time: batch.end_time,
+                    });
+                    next_chunk = chunk_time + options.min_interval;
+                }
+            }
+            _ => {
+                schedule.push(ScheduledUpdate {
+                    display_time,
+                    text: truncate(&batch.text, options.max_status_len),
+                    end_time: batch.end_time,
+                });
+
+                // halfway through, as long as both get a slot of their own
+                let window = window_end.saturating_sub(display_time);
+                if let Some(ref alternate) = batch.alternate {
+                    if window >= options.min_interval * 2 {
+                        schedule.push(ScheduledUpdate {
+                            display_time: display_time + window / 2,
+                            text: truncate(alternate, options.max_status_len),
+                            end_time: batch.end_time,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    schedule
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ConfigBuilder, SpotifyConfig},
+        test_util::line,
+    };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn options() -> ScheduleOptions {
+        ScheduleOptions {
+            min_interval: Duration::ZERO,
+            long_lines: LongLineMode::Truncate,
+            max_status_len: MAX_STATUS_LEN,
+            scroll_width: MAX_STATUS_LEN,
+            scroll_step: 32,
+            lookahead_gap: None,
+            title_gap: None,
+            max_batch_lines: None,
+            batch_separator: BatchSeparator::Period,
+            short_line_len: 20,
+            min_line_duration: None,
+            section_format: None,
+            alternate_romanized: false,
+            line_stride: 1,
+            side_by_side_translation: false,
+            trim_overlap: false,
+        }
+    }
+
+    fn texts(schedule: &[ScheduledUpdate]) -> Vec<&str> {
+        schedule.iter().map(|update| update.text.as_str()).collect()
+    }
+
+    #[test]
+    fn overlapping_repeats_are_scheduled_once() {
+        let lines = [
+            line("la la", 0, 4000),
+            line("la la", 1000, 2000),
+            line("backing", 2000, 3000),
+            line("next", 5000, 6000),
+        ];
+
+        let schedule = build_schedule(&lines, None, Duration::ZERO, None, &options());
+
+        assert_eq!(texts(&schedule), ["la la", "backing", "next"]);
+    }
+
+    #[test]
+    fn bursts_are_capped_at_max_batch_lines() {
+        let mut lines = (0..10)
+            .map(|index| line(&format!("line {}", index), index * 100, index * 100 + 100))
+            .collect::<Vec<_>>();
+        lines.push(line("after", 5000, 6000));
+        let options = ScheduleOptions {
+            min_interval: ms(2000),
+            max_batch_lines: Some(3),
+            ..options()
+        };
+
+        let schedule = build_schedule(&lines, None, Duration::ZERO, None, &options);
+
+        assert_eq!(texts(&schedule), ["…line 7. line 8. line 9", "after"]);
+    }
+
+    #[test]
+    fn rebuilding_mid_song_skips_lines_already_shown() {
+        let lines = [
+            line("first", 0, 1000),
+            line("second", 1000, 2000),
+            line("third", 2000, 3000),
+        ];
+
+        // "second" went out at 1000, then the schedule is rebuilt
+        let rebuilt = build_schedule(&lines, None, ms(1500), Some(ms(1000)), &options());
+        assert_eq!(texts(&rebuilt), ["third"]);
+
+        let fresh = build_schedule(&lines, None, ms(1500), None, &options());
+        assert_eq!(texts(&fresh), ["second", "third"]);
+    }
+
+    #[test]
+    fn flash_lines_merge_into_the_next_one() {
+        let lines = [
+            line("oh", 0, 100),
+            line("a line", 100, 2000),
+            line("last", 3000, 4000),
+        ];
+        let options = ScheduleOptions {
+            min_line_duration: Some(ms(200)),
+            ..options()
+        };
+
+        let schedule = build_schedule(&lines, None, Duration::ZERO, None, &options);
+
+        assert_eq!(texts(&schedule), ["oh. a line", "last"]);
+        assert_eq!(schedule[0].display_time, Duration::ZERO);
+        assert_eq!(schedule[0].end_time, ms(2000));
+    }
+
+    #[test]
+    fn line_stride_keeps_every_nth_line_and_the_last() {
+        let lines = (0..6)
+            .map(|index| {
+                line(
+                    &format!("line {}", index),
+                    index * 1000,
+                    index * 1000 + 1000,
+                )
+            })
+            .collect::<Vec<_>>();
+        let shown = |line_stride| {
+            let options = ScheduleOptions {
+                line_stride,
+                ..options()
+            };
+            let schedule = build_schedule(&lines, None, Duration::ZERO, None, &options);
+            texts(&schedule)
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(shown(1).len(), 6);
+        assert_eq!(shown(2), ["line 1", "line 3", "line 5"]);
+        assert_eq!(shown(3), ["line 2", "line 5"]);
+        assert_eq!(shown(4), ["line 3", "line 5"]);
+        // nothing to skip with a stride of 0
+        assert_eq!(shown(0), shown(1));
+    }
+
+    #[test]
+    fn trims_lines_the_previous_update_ended_with() {
+        assert_eq!(
+            trim_overlap("a. b", "b. c", BatchSeparator::Period).as_deref(),
+            Some("c")
+        );
+        assert_eq!(
+            trim_overlap("…a. b. c", "b. c. d", BatchSeparator::Period).as_deref(),
+            Some("d")
+        );
+        assert_eq!(trim_overlap("a. b", "c. d", BatchSeparator::Period), None);
+        // nothing would be left
+        assert_eq!(trim_overlap("a. b", "b", BatchSeparator::Period), None);
+
+        let lines = [
+            line("x", 0, 400),
+            line("y", 500, 1000),
+            line("y", 2000, 2100),
+            line("z", 2100, 3000),
+        ];
+        let options = ScheduleOptions {
+            min_interval: ms(2000),
+            trim_overlap: true,
+            ..options()
+        };
+        let schedule = build_schedule(&lines, None, Duration::ZERO, None, &options);
+        assert_eq!(texts(&schedule), ["x. y", "z"]);
+    }
+
+    #[test]
+    fn long_lines_are_cut_at_the_configured_length() {
+        let mut config = ConfigBuilder::new(SpotifyConfig::new("id", "secret"))
+            .build()
+            .unwrap();
+        config.display.max_status_len = 20;
+        let options = ScheduleOptions::from_config(&config);
+        let lines = [line("a line that goes on for a good while", 0, 1000)];
+
+        let schedule = build_schedule(&lines, None, Duration::ZERO, None, &options);
+
+        assert_eq!(texts(&schedule), ["a line that goes on…"]);
+    }
+
+    #[test]
+    fn short_lines_are_batched_with_the_compact_separator() {
+        let short = [line("oh", 0, 100), line("yeah", 100, 200)];
+        let long = [
+            line("a line long enough", 0, 100),
+            line("to need the full stop", 100, 200),
+        ];
+        let batched = |lines: &[TimedLine], batch_separator| {
+            let options = ScheduleOptions {
+                min_interval: ms(1000),
+                batch_separator,
+                ..options()
+            };
+            let schedule = build_schedule(lines, None, Duration::ZERO, None, &options);
+            texts(&schedule).concat()
+        };
+
+        assert_eq!(batched(&short, BatchSeparator::Period), "oh. yeah");
+        assert_eq!(batched(&short, BatchSeparator::Slash), "oh / yeah");
+        assert_eq!(batched(&short, BatchSeparator::Dot), "oh · yeah");
+        for separator in [
+            BatchSeparator::Period,
+            BatchSeparator::Slash,
+            BatchSeparator::Dot,
+        ] {
+            assert_eq!(
+                batched(&long, separator),
+                "a line long enough. to need the full stop"
+            );
+        }
+    }
+}
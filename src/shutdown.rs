@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// Tells the loops when to wrap up, so they can clean up and return instead
+/// of the process being killed halfway through.
+#[derive(Debug, Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Requests shutdown once Ctrl+C is pressed. Only install this once
+    /// nothing is blocking on input anymore, Ctrl+C no longer kills the
+    /// process after.
+    pub fn on_ctrl_c() -> Self {
+        let (sender, shutdown) = Self::channel();
+
+        tokio::spawn(async move {
+            match tokio::signal::ctrl_c().await {
+                Ok(()) => {
+                    println!("Shutting down");
+                    let _ = sender.send(true);
+                }
+                Err(err) => {
+                    eprintln!("Failed to listen for Ctrl+C: {}", err);
+                    // keep the sender around so nobody mistakes this for a shutdown
+                    std::future::pending::<()>().await;
+                }
+            }
+        });
+
+        shutdown
+    }
+
+    /// Shutdown that is requested by sending `true`, e.g. once there is
+    /// nothing left to do.
+    pub fn channel() -> (watch::Sender<bool>, Self) {
+        let (sender, receiver) = watch::channel(false);
+        (sender, Self(receiver))
+    }
+
+    /// Resolves once shutdown is requested.
+    pub async fn requested(&mut self) {
+        if self.0.wait_for(|&requested| requested).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Sleeps for `duration`. Returns `false` if shutdown was requested
+    /// in the meantime.
+    pub async fn sleep(&mut self, duration: Duration) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => true,
+            _ = self.requested() => false,
+        }
+    }
+}
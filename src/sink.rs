@@ -0,0 +1,225 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    activity::ActivitySink,
+    bio::BioSink,
+    config::{Config, SinkKind},
+    discord::{http_client, RateLimiter},
+    error::DyricsError,
+    file::FileSink,
+    playback::CurrentPlayback,
+    recorder::Recorder,
+    webhook::WebhookSink,
+};
+
+/// Somewhere the current status gets mirrored to.
+#[async_trait]
+pub trait StatusSink: Send {
+    fn name(&self) -> &str;
+
+    /// Called whenever the track changes or the position jumps, before the
+    /// next update for it is sent.
+    fn set_playback(&mut self, _playback: &CurrentPlayback) {}
+
+    /// When the text sent next stops being relevant, for sinks that can
+    /// clear it on their own.
+    fn set_expiry(&mut self, _expires_at: Option<SystemTime>) {}
+
+    /// How long an update takes to show up, so it can be sent that much early.
+    fn latency(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    /// Shows `text` on the sink. Returns `false` if the update was skipped,
+    /// either because it is already showing or the sink is out of capacity.
+    async fn send_update(&mut self, text: &str, emoji: &str) -> Result<bool, DyricsError>;
+
+    async fn clear_status(&mut self) -> Result<(), DyricsError>;
+
+    /// Clears the status one last time before exiting. Unlike with
+    /// [`Self::clear_status`] nobody asks again, so a clear that can't go out
+    /// is an error instead of being held back.
+    async fn clear_on_shutdown(&mut self) -> Result<(), DyricsError> {
+        self.clear_status().await
+    }
+}
+
+/// For sinks that can't show the emoji on its own.
+pub fn prepend_emoji(text: &str, emoji: &str) -> String {
+    if emoji.is_empty() {
+        text.to_string()
+    } else {
+        format!("{} {}", emoji, text)
+    }
+}
+
+/// The requests sent within the last `window`, for sinks that may only send
+/// `max_requests` of them in that time.
+#[derive(Debug)]
+pub struct RequestWindow {
+    max_requests: usize,
+    window: Duration,
+    sent_at: VecDeque<Instant>,
+}
+
+impl RequestWindow {
+    pub fn new(max_requests: usize, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            sent_at: VecDeque::new(),
+        }
+    }
+
+    /// Counts a request sent at `sent_at`, whether or not there was capacity
+    /// for it.
+    pub fn record(&mut self, sent_at: Instant) {
+        self.sent_at.push_back(sent_at);
+    }
+
+    /// Forgets the requests that fell out of the window by `now`.
+    fn prune(&mut self, now: Instant) {
+        while let Some(&oldest) = self.sent_at.front() {
+            if now.duration_since(oldest) < self.window {
+                break;
+            }
+            self.sent_at.pop_front();
+        }
+    }
+
+    pub fn has_capacity(&mut self) -> bool {
+        self.prune(Instant::now());
+        self.sent_at.len() < self.max_requests
+    }
+
+    /// When the next request fits in.
+    pub fn next_capacity(&mut self) -> Instant {
+        let now = Instant::now();
+        self.prune(now);
+        match self.sent_at.len().checked_sub(self.max_requests) {
+            Some(index) => self.sent_at[index] + self.window,
+            None => now,
+        }
+    }
+
+    /// How long ago the requests were sent, oldest first.
+    pub fn ages(&self) -> Vec<Duration> {
+        let now = Instant::now();
+        self.sent_at
+            .iter()
+            .map(|&sent_at| now.duration_since(sent_at))
+            .collect()
+    }
+
+    /// Takes over the requests sent `ages` ago that are still in the window.
+    pub fn restore(&mut self, ages: impl IntoIterator<Item = Duration>) {
+        let now = Instant::now();
+        let mut sent_at = ages
+            .into_iter()
+            .filter(|&age| age < self.window)
+            .filter_map(|age| now.checked_sub(age))
+            .collect::<Vec<_>>();
+        sent_at.sort();
+
+        self.sent_at = sent_at.into();
+    }
+}
+
+pub fn build_sinks(
+    config: &Config,
+    recorder: &Recorder,
+) -> Result<Vec<Box<dyn StatusSink>>, DyricsError> {
+    let mut sinks = Vec::<Box<dyn StatusSink>>::new();
+    for kind in &config.sinks {
+        match kind {
+            SinkKind::Discord => {
+                if config.discord.tokens.is_empty() {
+                    return Err(DyricsError::Config(
+                        "the discord sink needs discord.token".to_string(),
+                    ));
+                }
+                // numbered from 1 as they appear in the config
+                let several = config.discord.tokens.len() > 1;
+                for (index, token) in config.discord.tokens.iter().enumerate() {
+                    sinks.push(Box::new(
+                        RateLimiter::new(
+                            http_client(&config.discord)?,
+                            &config.discord,
+                            token,
+                            several.then_some(index + 1),
+                            recorder.clone(),
+                        )
+                        .with_max_status_len(config.display.max_status_len),
+                    ));
+                }
+            }
+            SinkKind::Bio => {
+                let bio = config.bio.as_ref().ok_or_else(|| {
+                    DyricsError::Config("the bio sink needs a [bio] section".to_string())
+                })?;
+                if config.discord.tokens.is_empty() {
+                    return Err(DyricsError::Config(
+                        "the bio sink needs discord.token".to_string(),
+                    ));
+                }
+                let several = config.discord.tokens.len() > 1;
+                for (index, token) in config.discord.tokens.iter().enumerate() {
+                    sinks.push(Box::new(BioSink::new(
+                        http_client(&config.discord)?,
+                        &config.discord,
+                        bio,
+                        token,
+                        several.then_some(index + 1),
+                    )));
+                }
+            }
+            SinkKind::Webhook => {
+                let webhook = config.webhook.as_ref().ok_or_else(|| {
+                    DyricsError::Config("the webhook sink needs a [webhook] section".to_string())
+                })?;
+                if reqwest::Url::parse(&webhook.url).is_err() {
+                    return Err(DyricsError::Config(
+                        "webhook.url is not a valid url".to_string(),
+                    ));
+                }
+                sinks.push(Box::new(WebhookSink::new(
+                    http_client(&config.discord)?,
+                    webhook,
+                )));
+            }
+            SinkKind::File => {
+                let file = config.file.as_ref().ok_or_else(|| {
+                    DyricsError::Config("the file sink needs a [file] section".to_string())
+                })?;
+                sinks.push(Box::new(FileSink::new(file)));
+            }
+            SinkKind::Activity => {
+                let activity = config.activity.as_ref().ok_or_else(|| {
+                    DyricsError::Config("the activity sink needs an [activity] section".to_string())
+                })?;
+                sinks.push(Box::new(ActivitySink::new(activity)));
+            }
+            #[cfg(feature = "notifications")]
+            SinkKind::Notification => {
+                sinks.push(Box::new(crate::notification::NotificationSink::new(
+                    &config.notification,
+                    crate::status::TrackFormat::from_config(&config.display),
+                )));
+            }
+            #[cfg(not(feature = "notifications"))]
+            SinkKind::Notification => {
+                return Err(DyricsError::Config(
+                    "the notification sink needs dyrics built with the \"notifications\" feature"
+                        .to_string(),
+                ));
+            }
+        }
+    }
+
+    Ok(sinks)
+}
@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rspotify::model::FullTrack;
+
+/// What a [`PlaybackSource`] saw when it was polled.
+#[derive(Debug, Clone)]
+pub enum PlaybackStatus {
+    Playing {
+        track: Box<FullTrack>,
+        position: Duration,
+        /// The name of the playlist, album or artist it is played from
+        context: Option<String>,
+    },
+    /// Paused, nothing playing at all, or playing something that isn't a
+    /// track
+    Stopped,
+}
+
+/// Somewhere to find out what is playing.
+#[async_trait]
+pub trait PlaybackSource: Send + Sync {
+    /// Returns `None` if something is playing but the source can't tell what,
+    /// e.g. in a private session. Whatever was playing before is kept in that
+    /// case. Nothing playing at all, like with no player open, is
+    /// [`PlaybackStatus::Stopped`].
+    async fn poll(&self) -> eyre::Result<Option<PlaybackStatus>>;
+
+    /// Token the lyrics provider is authenticated with.
+    async fn access_token(&self) -> eyre::Result<String>;
+}
@@ -0,0 +1,689 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use eyre::WrapErr as _;
+use reqwest::{StatusCode, Url};
+use rspotify::{
+    clients::{BaseClient as _, OAuthClient as _},
+    http::HttpError,
+    model::{
+        AdditionalType, ArtistId, Context, CurrentlyPlayingContext, FullTrack, Market,
+        PlayableItem, PlaylistId, Type,
+    },
+    AuthCodeSpotify, ClientError, ClientResult, Credentials, OAuth, Token,
+};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncRead, AsyncWrite, AsyncWriteExt as _, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+    task::JoinSet,
+};
+use tokio_rustls::{
+    rustls::{
+        pki_types::{CertificateDer, PrivatePkcs8KeyDer},
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+use crate::{
+    cert,
+    config::{SpotifyConfig, PLAYBACK_STATE_SCOPE, REQUIRED_SCOPE},
+    error::DyricsError,
+    source::{PlaybackSource, PlaybackStatus},
+};
+
+fn scopes(config: &SpotifyConfig) -> HashSet<String> {
+    match config.scopes {
+        Some(ref scopes) => scopes.iter().cloned().collect(),
+        None if config.playback_context => {
+            HashSet::from([REQUIRED_SCOPE.to_string(), PLAYBACK_STATE_SCOPE.to_string()])
+        }
+        None => HashSet::from([REQUIRED_SCOPE.to_string()]),
+    }
+}
+
+/// How often `spotify.token_command` is run for a fresh access token. Spotify's
+/// last an hour.
+const TOKEN_COMMAND_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Creates a Spotify client and makes sure it is authenticated.
+pub async fn create_client(config: &SpotifyConfig) -> eyre::Result<AuthCodeSpotify> {
+    let spotify = AuthCodeSpotify::with_config(
+        Credentials::new(&config.client_id, &config.client_secret),
+        OAuth {
+            redirect_uri: config.redirect_uri.clone(),
+            scopes: scopes(config),
+            ..Default::default()
+        },
+        rspotify::Config {
+            token_cached: config.token_cache.is_some() && config.token_command.is_none(),
+            cache_path: config.token_cache.clone().unwrap_or_default(),
+            // the token command does the refreshing
+            token_refreshing: config.token_command.is_none(),
+            ..Default::default()
+        },
+    );
+    if let Some(ref command) = config.token_command {
+        let token = run_token_command(command, config.http_timeout).await?;
+        set_token(&spotify, Some(token)).await?;
+        tokio::spawn(refresh_from_command(
+            spotify.clone(),
+            command.clone(),
+            config.http_timeout,
+        ));
+        return Ok(spotify);
+    }
+    if login_from_cache(&spotify, config).await? {
+        return Ok(spotify);
+    }
+
+    let authorize_url = spotify.get_authorize_url(false)?;
+    if config.headless {
+        eyre::bail!(
+            "no usable spotify login in {}, and spotify.headless keeps dyrics from asking for one. \
+             Log in by running dyrics once without spotify.headless, or by opening {}",
+            config.token_cache.clone().unwrap_or_default().display(),
+            authorize_url
+        );
+    }
+
+    if config.callback_server {
+        let callback_url = wait_for_callback(config, &authorize_url).await?;
+        let code = spotify
+            .parse_response_code(&callback_url)
+            .ok_or_else(|| eyre::eyre!("spotify redirected without a valid authorization code"))?;
+        with_login_retries(config, || spotify.request_token(&code)).await?;
+    } else {
+        let code = spotify.get_code_from_user(&authorize_url)?;
+        with_login_retries(config, || spotify.request_token(&code)).await?;
+    }
+
+    Ok(spotify)
+}
+
+/// Runs `spotify.token_command` for an access token, which is whatever it
+/// prints.
+async fn run_token_command(command: &[String], timeout: Duration) -> eyre::Result<Token> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| eyre::eyre!("spotify.token_command is empty"))?;
+    let output = tokio::time::timeout(
+        timeout,
+        tokio::process::Command::new(program)
+            .args(args)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .wrap_err_with(|| format!("spotify.token_command took longer than {:?}", timeout))?
+    .wrap_err_with(|| format!("failed to run spotify.token_command \"{}\"", program))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => eyre::bail!("spotify.token_command failed with {}", output.status),
+            stderr => eyre::bail!(
+                "spotify.token_command failed with {}: {}",
+                output.status,
+                stderr
+            ),
+        }
+    }
+    let access_token = String::from_utf8(output.stdout)
+        .wrap_err("spotify.token_command printed something that isn't text")?
+        .trim()
+        .to_string();
+    if access_token.is_empty() || access_token.contains(char::is_whitespace) {
+        eyre::bail!("spotify.token_command has to print the access token and nothing else");
+    }
+
+    Ok(Token {
+        access_token,
+        ..Default::default()
+    })
+}
+
+/// Keeps the token fresh by running `spotify.token_command` again every
+/// [`TOKEN_COMMAND_INTERVAL`].
+async fn refresh_from_command(spotify: AuthCodeSpotify, command: Vec<String>, timeout: Duration) {
+    loop {
+        tokio::time::sleep(TOKEN_COMMAND_INTERVAL).await;
+        // the token before may well last until the next try
+        let result = match run_token_command(&command, timeout).await {
+            Ok(token) => set_token(&spotify, Some(token)).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            tracing::warn!("{:#}, keeping the access token from before", err);
+        }
+    }
+}
+
+/// Logs in with the token cached by an earlier run, refreshing it if it
+/// expired. Returns `false` if there is none or it can't be refreshed anymore.
+async fn login_from_cache(spotify: &AuthCodeSpotify, config: &SpotifyConfig) -> eyre::Result<bool> {
+    // a missing or unreadable cache just means logging in again
+    let Ok(Some(token)) = spotify.read_token_cache(true).await else {
+        return Ok(false);
+    };
+    let expired = token.is_expired();
+    set_token(spotify, Some(token)).await?;
+    if !expired {
+        return Ok(true);
+    }
+
+    match with_login_retries(config, || spotify.refetch_token()).await {
+        Ok(Some(token)) => {
+            set_token(spotify, Some(token)).await?;
+            spotify.write_token_cache().await?;
+            Ok(true)
+        }
+        // cached without a refresh token
+        Ok(None) => Ok(false),
+        Err(DyricsError::Auth { ref source, .. })
+            if source
+                .downcast_ref::<ClientError>()
+                .is_some_and(is_login_rejected) =>
+        {
+            tracing::warn!("The cached spotify login was rejected, logging in again");
+            Ok(false)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Runs a login request, trying again after timeouts and temporary failures
+/// with a growing delay, up to [`SpotifyConfig::login_attempts`] times.
+async fn with_login_retries<T, F, Fut>(config: &SpotifyConfig, request: F) -> Result<T, DyricsError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, ClientError>>,
+{
+    let attempts = config.login_attempts.max(1);
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let err: Box<dyn std::error::Error + Send + Sync> =
+            match tokio::time::timeout(config.http_timeout, request()).await {
+                Ok(Ok(value)) => return Ok(value),
+                Ok(Err(err)) if is_temporary_error(&err) => err.into(),
+                Ok(Err(err)) => {
+                    return Err(DyricsError::Auth {
+                        attempts: attempt,
+                        source: err.into(),
+                    })
+                }
+                Err(elapsed) => elapsed.into(),
+            };
+        if attempt >= attempts {
+            return Err(DyricsError::Auth {
+                attempts: attempt,
+                source: err,
+            });
+        }
+
+        tracing::warn!(
+            "Logging in with spotify failed: {}, retrying in {}s",
+            err,
+            delay.as_secs()
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+/// How long the callback server waits for Spotify to redirect back.
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Serves `redirect_uri` until Spotify sends the browser back to it, and
+/// returns the url it was called with.
+async fn wait_for_callback(config: &SpotifyConfig, authorize_url: &str) -> eyre::Result<String> {
+    let address = config.callback_address()?;
+    let listener = TcpListener::bind(address)
+        .await
+        .wrap_err("failed to start the spotify callback server")?;
+    let redirect_uri = Url::parse(&config.redirect_uri)?;
+    let tls = match redirect_uri.scheme() {
+        "https" => Some(tls_acceptor(address.ip())?),
+        _ => None,
+    };
+
+    println!("Open this url to log in with spotify: {}", authorize_url);
+
+    // browsers open connections they may never send anything on, so every
+    // connection is answered on its own and one going nowhere holds up nothing
+    let wait = async {
+        let mut connections = JoinSet::new();
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        connections.spawn(answer_connection(stream, tls.clone(), redirect_uri.clone()));
+                    }
+                    Err(err) => tracing::warn!("The spotify callback server failed to accept a connection: {}", err),
+                },
+                Some(answered) = connections.join_next() => {
+                    // anything else is some other request, or no request at all
+                    if let Ok(Ok(Some(callback_url))) = answered {
+                        return callback_url;
+                    }
+                }
+            }
+        }
+    };
+    let callback_url = tokio::time::timeout(CALLBACK_TIMEOUT, wait)
+        .await
+        .map_err(|_| {
+            eyre::eyre!(
+                "spotify didn't redirect back within {} minutes, run dyrics again to log in",
+                CALLBACK_TIMEOUT.as_secs() / 60
+            )
+        })?;
+    Ok(callback_url.to_string())
+}
+
+async fn answer_connection(
+    stream: TcpStream,
+    tls: Option<TlsAcceptor>,
+    redirect_uri: Url,
+) -> eyre::Result<Option<Url>> {
+    match tls {
+        // browsers drop the connection until the certificate is accepted
+        Some(tls) => answer_callback(tls.accept(stream).await?, &redirect_uri).await,
+        None => answer_callback(stream, &redirect_uri).await,
+    }
+}
+
+fn tls_acceptor(ip: IpAddr) -> eyre::Result<TlsAcceptor> {
+    let cert = cert::self_signed(ip)?;
+    let server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(cert.cert)],
+            PrivatePkcs8KeyDer::from(cert.key).into(),
+        )
+        .wrap_err("failed to set up tls for the spotify callback server")?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Answers a single request to the callback server, returning the url if it
+/// was the redirect.
+async fn answer_callback<S>(mut stream: S, redirect_uri: &Url) -> eyre::Result<Option<Url>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request_line = String::new();
+    BufReader::new(&mut stream)
+        .read_line(&mut request_line)
+        .await?;
+    // a connection opened ahead of time and closed unused
+    if request_line.trim().is_empty() {
+        return Ok(None);
+    }
+
+    // e.g. "GET /callback?code=...&state=... HTTP/1.1"
+    let callback_url = request_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|target| redirect_uri.join(target).ok());
+    let Some(callback_url) = callback_url else {
+        stream
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n")
+            .await?;
+        stream.shutdown().await?;
+        return Ok(None);
+    };
+    if callback_url.path() != redirect_uri.path() {
+        // most likely the browser asking for a favicon
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\n")
+            .await?;
+        stream.shutdown().await?;
+        return Ok(None);
+    }
+
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n\
+              Logged in to dyrics, you can close this tab now.",
+        )
+        .await?;
+    stream.shutdown().await?;
+
+    Ok(Some(callback_url))
+}
+
+async fn set_token(spotify: &AuthCodeSpotify, token: Option<Token>) -> eyre::Result<()> {
+    *spotify
+        .token
+        .lock()
+        .await
+        .map_err(|_| eyre::eyre!("failed to lock the spotify token"))? = token;
+    Ok(())
+}
+
+pub async fn access_token(spotify: &AuthCodeSpotify) -> eyre::Result<String> {
+    spotify
+        .token
+        .lock()
+        .await
+        .map_err(|_| eyre::eyre!("failed to lock the spotify token"))?
+        .as_ref()
+        .map(|token| token.access_token.clone())
+        .ok_or_else(|| eyre::eyre!("not authenticated with spotify"))
+}
+
+/// Whether Spotify is only unavailable for now, e.g. during an outage.
+pub fn is_temporary_error(err: &ClientError) -> bool {
+    let ClientError::Http(ref err) = *err else {
+        return false;
+    };
+
+    match **err {
+        HttpError::Client(ref err) => err.is_timeout() || err.is_connect(),
+        HttpError::StatusCode(ref response) => {
+            response.status().is_server_error()
+                || response.status() == StatusCode::TOO_MANY_REQUESTS
+        }
+    }
+}
+
+/// Where Spotify hands out and refreshes access tokens, under `auth_base_url`.
+const TOKEN_PATH: &str = "/api/token";
+
+/// Whether Spotify turned down the login for good: there is no refresh token,
+/// or the token endpoint rejected it (`invalid_grant`), e.g. because access was
+/// revoked. Spotify says so with a 400, the body with the reason is gone by
+/// the time the error gets here.
+pub fn is_login_rejected(err: &ClientError) -> bool {
+    match *err {
+        ClientError::InvalidToken => true,
+        ClientError::Http(ref err) => matches!(
+            **err,
+            HttpError::StatusCode(ref response)
+                if response.status() == StatusCode::BAD_REQUEST
+                    && response.url().path().ends_with(TOKEN_PATH)
+        ),
+        _ => false,
+    }
+}
+
+/// Whether Spotify turned a request down, e.g. over an access token that
+/// expired in the meantime. Unlike a rejected login, see
+/// [`is_login_rejected`], the next request may well go through.
+pub fn is_request_rejected(err: &ClientError) -> bool {
+    let ClientError::Http(ref err) = *err else {
+        return false;
+    };
+
+    matches!(
+        **err,
+        HttpError::StatusCode(ref response)
+            if matches!(response.status(), StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED)
+    )
+}
+
+/// Polls the Spotify Web API for what is playing.
+pub struct SpotifySource {
+    spotify: AuthCodeSpotify,
+    timeout: Duration,
+    /// Whether Spotify is leaving out what is playing, e.g. in a private session
+    restricted: AtomicBool,
+    playback_context: bool,
+    /// Names of the contexts played from so far by their uri
+    context_names: RwLock<HashMap<String, Option<String>>>,
+}
+
+impl SpotifySource {
+    pub fn new(spotify: AuthCodeSpotify, config: &SpotifyConfig) -> Self {
+        Self {
+            spotify,
+            timeout: config.http_timeout,
+            restricted: AtomicBool::new(false),
+            playback_context: config.playback_context,
+            context_names: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// What is playing. With `playback_context` it comes from the playback
+    /// state, which says what the track is played from where the currently
+    /// playing endpoint often doesn't.
+    async fn currently_playing(&self) -> ClientResult<Option<CurrentlyPlayingContext>> {
+        // with a market spotify relinks tracks and says what they were
+        // linked from, which is what the lyrics are found by
+        let market = Some(Market::FromToken);
+        if !self.playback_context {
+            return self
+                .spotify
+                .current_playing(market, None::<Vec<&AdditionalType>>)
+                .await;
+        }
+
+        let playback = self
+            .spotify
+            .current_playback(market, None::<Vec<&AdditionalType>>)
+            .await?;
+        Ok(playback.map(|playback| CurrentlyPlayingContext {
+            context: playback.context,
+            timestamp: playback.timestamp,
+            progress: playback.progress,
+            is_playing: playback.is_playing,
+            item: playback.item,
+            currently_playing_type: playback.currently_playing_type,
+            actions: playback.actions,
+        }))
+    }
+
+    /// The name of what `track` is played from. Spotify only gives the uri,
+    /// so playlists and artists are looked up once.
+    async fn context_name(&self, context: &Context, track: &FullTrack) -> Option<String> {
+        match context._type {
+            Type::Album => return Some(track.album.name.clone()),
+            Type::Collection => return Some("Liked Songs".to_string()),
+            Type::Playlist | Type::Artist => {}
+            _ => return None,
+        }
+        if let Some(name) = self.context_names.read().await.get(&context.uri) {
+            return name.clone();
+        }
+
+        let name = match context._type {
+            Type::Playlist => match PlaylistId::from_uri(&context.uri) {
+                Ok(id) => self
+                    .spotify
+                    .playlist(id, None, Some(Market::FromToken))
+                    .await
+                    .map(|playlist| playlist.name)
+                    .map_err(eyre::Report::from),
+                Err(err) => Err(err.into()),
+            },
+            _ => match ArtistId::from_uri(&context.uri) {
+                Ok(id) => self
+                    .spotify
+                    .artist(id)
+                    .await
+                    .map(|artist| artist.name)
+                    .map_err(eyre::Report::from),
+                Err(err) => Err(err.into()),
+            },
+        };
+        // not worth asking again, the status just goes without it
+        let name = name
+            .inspect_err(|err| tracing::warn!("Failed to look up {}: {}", context.uri, err))
+            .ok();
+        self.context_names
+            .write()
+            .await
+            .insert(context.uri.clone(), name.clone());
+        name
+    }
+}
+
+#[async_trait]
+impl PlaybackSource for SpotifySource {
+    async fn poll(&self) -> eyre::Result<Option<PlaybackStatus>> {
+        let Some(currently_playing) = tokio::time::timeout(self.timeout, self.currently_playing())
+            .await
+            .wrap_err("spotify request timed out")??
+        else {
+            // nothing at all, e.g. because spotify was closed. Counted like
+            // a pause, so a single empty answer doesn't clear the status
+            return Ok(Some(PlaybackStatus::Stopped));
+        };
+
+        if !currently_playing.is_playing {
+            return Ok(Some(PlaybackStatus::Stopped));
+        }
+        let position = currently_playing
+            .progress
+            .and_then(|progress| progress.to_std().ok());
+        let (track, position) = match (currently_playing.item, position) {
+            (Some(PlayableItem::Track(track)), Some(position)) => (track, position),
+            (Some(PlayableItem::Episode(_)), _) => return Ok(Some(PlaybackStatus::Stopped)),
+            // something is playing, but spotify won't say what or where, so
+            // the last playback is better than nothing
+            _ => {
+                if !self.restricted.swap(true, Ordering::Relaxed) {
+                    tracing::warn!(
+                        "Spotify is holding back what is playing, e.g. because of a private session. \
+                         Keeping the last status until it tells again"
+                    );
+                }
+                return Ok(None);
+            }
+        };
+        self.restricted.store(false, Ordering::Relaxed);
+
+        let context = match currently_playing.context {
+            Some(ref context) if self.playback_context => self.context_name(context, &track).await,
+            _ => None,
+        };
+        Ok(Some(PlaybackStatus::Playing {
+            track: Box::new(track),
+            position,
+            context,
+        }))
+    }
+
+    async fn access_token(&self) -> eyre::Result<String> {
+        access_token(&self.spotify).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::test_util::{response, serve, serve_with, Requests};
+
+    const TOKEN: &str = r#"{"access_token":"fresh","token_type":"Bearer","expires_in":3600,"scope":"user-read-currently-playing"}"#;
+
+    /// A token endpoint that fails with 503 `failures` times before handing out
+    /// a token. Returns its base url and the requests it got.
+    async fn serve_token(failures: usize) -> (String, Requests) {
+        let answered = AtomicUsize::new(0);
+        let (url, requests) = serve_with(move |_| {
+            Some(if answered.fetch_add(1, Ordering::SeqCst) < failures {
+                response("503 Service Unavailable", "", b"")
+            } else {
+                response("200 OK", "", TOKEN.as_bytes())
+            })
+        })
+        .await;
+        (format!("{}/", url), requests)
+    }
+
+    fn spotify(auth_base_url: String) -> AuthCodeSpotify {
+        AuthCodeSpotify::with_config(
+            Credentials::new("id", "secret"),
+            OAuth {
+                redirect_uri: "http://127.0.0.1:8888/callback".to_string(),
+                ..Default::default()
+            },
+            rspotify::Config {
+                auth_base_url,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn token_requests_are_retried_after_temporary_failures() {
+        let (url, requests) = serve_token(1).await;
+        let spotify = spotify(url);
+        let config = SpotifyConfig::new("id", "secret");
+
+        with_login_retries(&config, || spotify.request_token("code"))
+            .await
+            .unwrap();
+
+        let token = spotify.token.lock().await.unwrap().clone().unwrap();
+        assert_eq!(token.access_token, "fresh");
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn only_a_rejected_refresh_token_rejects_the_login() {
+        let (token_url, _) = serve("400 Bad Request", "", r#"{"error":"invalid_grant"}"#).await;
+        let (api_url, _) = serve("401 Unauthorized", "", "").await;
+        let spotify = AuthCodeSpotify::with_config(
+            Credentials::new("id", "secret"),
+            OAuth::default(),
+            rspotify::Config {
+                auth_base_url: format!("{}/", token_url),
+                api_base_url: format!("{}/v1/", api_url),
+                token_refreshing: false,
+                ..Default::default()
+            },
+        );
+        let token = Token {
+            access_token: "stale".to_string(),
+            refresh_token: Some("revoked".to_string()),
+            ..Default::default()
+        };
+        set_token(&spotify, Some(token)).await.unwrap();
+
+        let refresh = spotify.refetch_token().await.unwrap_err();
+        assert!(is_login_rejected(&refresh), "{}", refresh);
+
+        let poll = spotify
+            .current_playing(None, None::<Vec<&AdditionalType>>)
+            .await
+            .unwrap_err();
+        assert!(!is_login_rejected(&poll), "{}", poll);
+        assert!(is_request_rejected(&poll), "{}", poll);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_the_token_after_the_last_attempt() {
+        let (url, requests) = serve_token(usize::MAX).await;
+        let spotify = spotify(url);
+        let config = SpotifyConfig {
+            login_attempts: 2,
+            ..SpotifyConfig::new("id", "secret")
+        };
+
+        let err = with_login_retries(&config, || spotify.request_token("code"))
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, DyricsError::Auth { attempts: 2, .. }),
+            "{}",
+            err
+        );
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+}
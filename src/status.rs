@@ -0,0 +1,773 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime},
+};
+
+use rspotify::model::FullTrack;
+
+use crate::{
+    config::{
+        Config, DisplayConfig, DurationFormat, InvalidTokenBehavior, MatchMode, PastLyricsBehavior,
+    },
+    error::DyricsError,
+    lyrics::{Lyrics, TimedLine},
+    playback::{CurrentPlayback, SharedPlayback, STEP_INTERVAL},
+    profanity::ProfanityFilter,
+    recorder::{Event, Recorder},
+    schedule::{build_schedule, build_schedule_from, ScheduleOptions, ScheduledUpdate},
+    shutdown::Shutdown,
+    sink::StatusSink,
+    template::{format_duration, render},
+    text::{self, normalize, truncate},
+};
+
+/// How far the position may jump between two polls before it counts as a seek.
+const SEEK_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Longest the status loop sleeps, so it still notices new tracks and seeks.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How tracks are shown when there are no lyrics, see [`DisplayConfig::track_format`].
+#[derive(Debug, Clone)]
+pub struct TrackFormat {
+    template: String,
+    unknown_artist: Option<String>,
+    unknown_track: Option<String>,
+    duration_format: DurationFormat,
+}
+
+impl TrackFormat {
+    pub fn from_config(config: &DisplayConfig) -> Self {
+        Self {
+            template: config.track_format.clone(),
+            unknown_artist: config.unknown_artist.clone(),
+            unknown_track: config.unknown_track.clone(),
+            duration_format: config.duration_format,
+        }
+    }
+
+    /// Renders `track` as it plays at `position`.
+    pub fn render(&self, track: &FullTrack, context: Option<&str>, position: Duration) -> String {
+        let artists = track
+            .artists
+            .iter()
+            .map(|val| val.name.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let artists = match (artists.as_str(), self.unknown_artist.as_deref()) {
+            ("", Some(unknown_artist)) => unknown_artist,
+            (artists, _) => artists,
+        };
+        // without a placeholder for the name the artists take its place, so
+        // the status doesn't start with the separator in front of them
+        let (name, artists) = match (track.name.trim(), self.unknown_track.as_deref()) {
+            ("", Some(unknown_track)) => (unknown_track, artists),
+            ("", None) => (artists, ""),
+            (name, _) => (name, artists),
+        };
+        let duration = track.duration.to_std().unwrap_or_default();
+        let position = format_duration(position.min(duration), self.duration_format);
+        let duration = format_duration(duration, self.duration_format);
+        let progress = format!("{}/{}", position, duration);
+
+        normalize(&render(
+            &self.template,
+            &[
+                ("name", name),
+                ("artists", artists),
+                ("album", &track.album.name),
+                ("duration", &duration),
+                ("position", &position),
+                ("progress", &progress),
+                ("context", context.unwrap_or_default()),
+            ],
+        ))
+    }
+}
+
+/// The status shown when there are no lyrics to show.
+pub fn status_text(playback: &CurrentPlayback, format: &TrackFormat, max_len: usize) -> String {
+    truncate(
+        &format.render(
+            &playback.track,
+            playback.context.as_deref(),
+            playback.position,
+        ),
+        max_len,
+    )
+}
+
+/// Cleans up lyric lines before they are scheduled. Truncation happens later,
+/// once lines have been batched.
+///
+/// Blank lines, which usually mark a break between sections, are replaced with
+/// `blank_line` or dropped so the status doesn't go empty mid-song. Right to
+/// left lines are isolated if `isolate_rtl` is set, see [`text::isolate_rtl`].
+pub fn prepare_lines(
+    mut lines: Vec<TimedLine>,
+    profanity: Option<&ProfanityFilter>,
+    blank_line: Option<&str>,
+    isolate_rtl: bool,
+) -> Vec<TimedLine> {
+    lines.retain_mut(|line| {
+        if !line.text.trim().is_empty() {
+            return true;
+        }
+        match blank_line {
+            Some(blank_line) => {
+                line.text = blank_line.to_string();
+                line.word_starts.clear();
+                true
+            }
+            None => false,
+        }
+    });
+
+    for line in lines.iter_mut() {
+        line.text = prepare_text(&line.text, profanity);
+        line.romanized = line
+            .romanized
+            .as_deref()
+            .map(|romanized| prepare_text(romanized, profanity));
+        line.translated = line
+            .translated
+            .as_deref()
+            .map(|translated| prepare_text(translated, profanity));
+
+        if isolate_rtl {
+            line.text = text::isolate_rtl(&line.text);
+            line.translated = line.translated.as_deref().map(text::isolate_rtl);
+        }
+    }
+    lines
+}
+
+pub fn prepare_text(text: &str, profanity: Option<&ProfanityFilter>) -> String {
+    let text = normalize(text);
+    match profanity {
+        Some(profanity) => profanity.mask(&text),
+        None => text,
+    }
+}
+
+/// Turns a playback's lyrics into the updates that show them.
+pub struct Planner {
+    pub options: ScheduleOptions,
+    profanity: Option<ProfanityFilter>,
+    track_format: TrackFormat,
+    blank_line: Option<String>,
+    match_mode: MatchMode,
+    isolate_rtl: bool,
+}
+
+impl Planner {
+    pub fn from_config(config: &Config) -> Result<Self, DyricsError> {
+        Ok(Self {
+            options: ScheduleOptions::from_config(config),
+            profanity: ProfanityFilter::from_config(&config.profanity)?,
+            track_format: TrackFormat::from_config(&config.display),
+            blank_line: config.display.blank_line.clone(),
+            match_mode: config.lyrics.match_mode,
+            isolate_rtl: config.display.isolate_rtl,
+        })
+    }
+
+    fn lines(&self, lyrics: &Lyrics) -> Vec<TimedLine> {
+        prepare_lines(
+            lyrics.get_timed_lines(),
+            self.profanity.as_ref(),
+            self.blank_line.as_deref(),
+            self.isolate_rtl,
+        )
+    }
+
+    /// The updates that show `lyrics` from `position` onwards.
+    pub fn schedule(
+        &self,
+        lyrics: &Lyrics,
+        track: &FullTrack,
+        position: Duration,
+    ) -> Vec<ScheduledUpdate> {
+        let title = self.title(track, None, position);
+        build_schedule(
+            &self.lines(lyrics),
+            Some(&title),
+            position,
+            None,
+            &self.options,
+        )
+    }
+
+    fn title(&self, track: &FullTrack, context: Option<&str>, position: Duration) -> String {
+        prepare_text(
+            &self.track_format.render(track, context, position),
+            self.profanity.as_ref(),
+        )
+    }
+
+    /// The line at `position` the way the schedule would show it, picked like
+    /// [`Lyrics::get_text_at`] does.
+    fn current_text(&self, lines: &[TimedLine], position: Duration) -> Option<String> {
+        let distance = |line: &TimedLine| {
+            line.start_time.saturating_sub(position) + position.saturating_sub(line.end_time)
+        };
+        // of overlapping lines, the one that started last wins
+        let line = lines
+            .iter()
+            .filter(|line| distance(line).is_zero())
+            .max_by_key(|line| line.start_time)
+            .or_else(|| match self.match_mode {
+                MatchMode::Nearest => lines.iter().min_by_key(|line| distance(line)),
+                MatchMode::Strict => None,
+            })?;
+        build_schedule(
+            std::slice::from_ref(line),
+            None,
+            Duration::ZERO,
+            None,
+            &self.options,
+        )
+        .into_iter()
+        .next()
+        .map(|update| update.text)
+    }
+
+    /// `last_displayed` is passed on to [`build_schedule`].
+    pub fn plan(
+        &self,
+        playback: &CurrentPlayback,
+        track_changed: bool,
+        last_displayed: Option<Duration>,
+    ) -> Vec<ScheduledUpdate> {
+        let Some(ref lyrics) = playback.lyrics else {
+            return Vec::new();
+        };
+
+        let lines = self.lines(lyrics);
+        let title = self.title(
+            &playback.track,
+            playback.context.as_deref(),
+            playback.position,
+        );
+        let schedule = build_schedule(
+            &lines,
+            Some(&title),
+            playback.position,
+            last_displayed,
+            &self.options,
+        );
+
+        // when joining in the middle of a song, show where we are right away
+        // instead of waiting for the next line
+        let catch_up = self
+            .current_text(&lines, playback.position)
+            .filter(|text| !text.is_empty());
+        match (track_changed, catch_up, schedule.first()) {
+            (true, Some(catch_up), Some(first))
+                if first.display_time > playback.position && first.text != catch_up =>
+            {
+                // lines still going on, or failing that whatever comes next
+                let end_time = lines
+                    .iter()
+                    .filter(|line| line.start_time <= playback.position)
+                    .map(|line| line.end_time)
+                    .filter(|&end_time| end_time > playback.position)
+                    .max()
+                    .unwrap_or(first.display_time);
+                let mut schedule = build_schedule_from(
+                    &lines,
+                    Some(&title),
+                    playback.position,
+                    playback.position + self.options.min_interval,
+                    None,
+                    &self.options,
+                );
+                schedule.insert(
+                    0,
+                    ScheduledUpdate {
+                        display_time: playback.position,
+                        text: catch_up,
+                        end_time,
+                    },
+                );
+                schedule
+            }
+            _ => schedule,
+        }
+    }
+}
+
+fn seeked(last_position: Duration, position: Duration) -> bool {
+    position + SEEK_THRESHOLD < last_position || position > last_position + SEEK_THRESHOLD
+}
+
+pub async fn status_loop(
+    current_playback: SharedPlayback,
+    mut sinks: Vec<Box<dyn StatusSink>>,
+    config: &Config,
+    recorder: Recorder,
+    mut shutdown: Shutdown,
+) -> eyre::Result<()> {
+    let planner = Planner::from_config(config)?;
+    let track_format = TrackFormat::from_config(&config.display);
+    let mut last_generation = None;
+    let mut had_lyrics = false;
+    let mut last_position = Duration::ZERO;
+    let mut last_displayed = None;
+    let mut schedule = VecDeque::<ScheduledUpdate>::new();
+    let mut text = None;
+    let mut expires_at = None;
+    let mut stopped_at = None;
+    // shown in between the lyrics and clearing, see `DisplayConfig::fade_out`
+    let mut fade_title = None;
+    // the position as last seen and when it was, to tell where we are between steps
+    let mut position_seen: Option<(Duration, Instant)> = None;
+    // without stepping the position only moves with the syncs
+    let step = if config.spotify.use_stepping {
+        STEP_INTERVAL
+    } else {
+        config.spotify.sync_interval()
+    };
+
+    while !sinks.is_empty() {
+        let mut sleep_for = MAX_POLL_INTERVAL;
+        if let Some(ref playback) = *current_playback.read().await {
+            stopped_at = None;
+            let track_changed = last_generation != Some(playback.generation);
+            // a track is stored without lyrics until they are fetched
+            let lyrics_arrived = playback.lyrics.is_some() && !had_lyrics;
+            had_lyrics = playback.lyrics.is_some();
+            if track_changed || lyrics_arrived || seeked(last_position, playback.position) {
+                last_generation = Some(playback.generation);
+                for sink in sinks.iter_mut() {
+                    sink.set_playback(playback);
+                }
+                if track_changed || lyrics_arrived || playback.position < last_position {
+                    // a new song, or going back to lines worth seeing again
+                    last_displayed = None;
+                }
+                schedule = planner
+                    .plan(playback, track_changed || lyrics_arrived, last_displayed)
+                    .into();
+                recorder.record(|| Event::Schedule {
+                    generation: playback.generation,
+                    position: playback.position,
+                    updates: schedule.iter().cloned().collect(),
+                });
+                // the last line of the track before would stay up until
+                // the first one of this track otherwise
+                let announce = track_changed && config.display.title_on_track_change;
+                if playback.lyrics.is_none() || announce {
+                    text = Some(status_text(
+                        playback,
+                        &track_format,
+                        planner.options.max_status_len,
+                    ));
+                    expires_at = None;
+                }
+                fade_title = (playback.lyrics.is_some() && !config.display.fade_out.is_zero())
+                    .then(|| status_text(playback, &track_format, planner.options.max_status_len));
+                // joined after the last line, e.g. by seeking into the outro
+                if playback.lyrics.is_some() && schedule.is_empty() {
+                    match config.display.past_lyrics {
+                        PastLyricsBehavior::Keep => {}
+                        PastLyricsBehavior::Clear => text = None,
+                        PastLyricsBehavior::Title => {
+                            text = Some(status_text(
+                                playback,
+                                &track_format,
+                                planner.options.max_status_len,
+                            ));
+                            expires_at = None;
+                        }
+                    }
+                }
+            }
+
+            let seen_at = match position_seen {
+                Some((position, seen_at)) if position == playback.position => seen_at,
+                _ => Instant::now(),
+            };
+            position_seen = Some((playback.position, seen_at));
+            // the stepping only moves the position every so often
+            let position = (playback.position + seen_at.elapsed().min(step)).min(playback.duration);
+            // compared to where we thought we were, so that a sync catching
+            // up on a whole step isn't taken for a seek
+            last_position = position;
+
+            let latency = sinks
+                .iter()
+                .map(|sink| sink.latency())
+                .max()
+                .unwrap_or_default();
+            // without syncs the position is a guess, better to hold the
+            // line that is showing than to show the wrong ones
+            let stale = playback.is_stale(config.spotify.stale_after);
+            while let Some(update) = schedule.front().filter(|_| !stale) {
+                if update.display_time > position + latency {
+                    break;
+                }
+                if let Some(update) = schedule.pop_front() {
+                    expires_at = Some(SystemTime::now() + update.end_time.saturating_sub(position));
+                    last_displayed = Some(update.display_time);
+                    text = Some(update.text);
+                }
+            }
+
+            // wake up right when the next line is due, with nothing left to
+            // show there's only the playback to keep an eye on
+            if let Some(update) = schedule.front() {
+                sleep_for = update
+                    .display_time
+                    .saturating_sub(position + latency)
+                    .min(MAX_POLL_INTERVAL);
+            }
+        } else {
+            let stopped_for = stopped_at.get_or_insert_with(Instant::now).elapsed();
+            if stopped_for >= config.discord.clear_grace + config.display.fade_out {
+                last_generation = None;
+                last_displayed = None;
+                schedule.clear();
+                text = None;
+                position_seen = None;
+                fade_title = None;
+            } else if stopped_for >= config.discord.clear_grace {
+                // on the way out the lyrics make way for the track first
+                if let Some(title) = fade_title.take() {
+                    schedule.clear();
+                    text = Some(title);
+                    expires_at = None;
+                }
+            }
+        }
+
+        let update = match text {
+            Some(ref text) => Some((text.as_str(), config.display.emoji.as_str(), expires_at)),
+            None => config
+                .discord
+                .idle_status
+                .as_ref()
+                .map(|idle| (idle.text.as_str(), idle.emoji.as_str(), None)),
+        };
+
+        let mut index = 0;
+        while index < sinks.len() {
+            let sink = &mut sinks[index];
+            let result = match update {
+                Some((text, emoji, expires_at)) => {
+                    sink.set_expiry(expires_at);
+                    sink.send_update(text, emoji).await.map(drop)
+                }
+                None => sink.clear_status().await,
+            };
+
+            if let Err(ref err) = result {
+                recorder.record(|| Event::Error {
+                    context: sink.name().to_string(),
+                    message: err.to_string(),
+                });
+            }
+            match result {
+                Err(err @ DyricsError::DiscordAuth(_)) => {
+                    tracing::error!("{}, check discord.token in your config", err);
+                    // whatever the sink changed, like a bio, is worth putting
+                    // back if the token still allows it
+                    if let Err(err) = sink.clear_status().await {
+                        tracing::warn!("Failed to clear the {} status: {}", sink.name(), err);
+                    }
+                    match config.discord.on_invalid_token {
+                        InvalidTokenBehavior::Stop => {
+                            tracing::error!("No longer updating the {} status", sink.name());
+                            sinks.remove(index);
+                        }
+                        InvalidTokenBehavior::Exit => return Err(err.into()),
+                    }
+                }
+                // only this sink is affected, unlike with an invalid token
+                Err(err @ DyricsError::WebhookRejected(_)) => {
+                    tracing::error!("{}, no longer updating the {} status", err, sink.name());
+                    sinks.remove(index);
+                }
+                Err(err) => {
+                    // a slow or failing sink shouldn't take the others down with it
+                    tracing::warn!("Updating the {} status failed: {}", sink.name(), err);
+                    index += 1;
+                }
+                Ok(()) => index += 1,
+            }
+        }
+
+        if !shutdown.sleep(sleep_for).await {
+            // don't leave the last line up once we're gone
+            for sink in sinks.iter_mut() {
+                if let Err(err) = sink.clear_on_shutdown().await {
+                    tracing::warn!("Failed to clear the {} status: {}", sink.name(), err);
+                }
+            }
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use serde_json::json;
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::{
+        config::{ConfigBuilder, SpotifyConfig},
+        test_util::line,
+    };
+
+    fn track(name: &str, artists: &[&str]) -> FullTrack {
+        let artists = artists
+            .iter()
+            .map(|artist| json!({ "name": artist, "external_urls": {} }))
+            .collect::<Vec<_>>();
+        serde_json::from_value(json!({
+            "album": {
+                "name": "Album",
+                "artists": [],
+                "external_urls": {},
+                "images": []
+            },
+            "artists": artists,
+            "disc_number": 1,
+            "duration_ms": 180000,
+            "explicit": false,
+            "external_ids": {},
+            "external_urls": {},
+            "is_local": false,
+            "name": name,
+            "popularity": 0,
+            "track_number": 1
+        }))
+        .unwrap()
+    }
+
+    fn texts(lines: &[TimedLine]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn tracks_without_artists_leave_out_the_separator() {
+        let mut config = DisplayConfig::default();
+        let track = track("Song", &[]);
+
+        assert_eq!(
+            TrackFormat::from_config(&config).render(&track, None, Duration::ZERO),
+            "Song"
+        );
+
+        config.unknown_artist = Some("Unknown Artist".to_string());
+        assert_eq!(
+            TrackFormat::from_config(&config).render(&track, None, Duration::ZERO),
+            "Song - Unknown Artist"
+        );
+    }
+
+    #[test]
+    fn renders_the_position_and_progress() {
+        let config = DisplayConfig {
+            track_format: "{position} {progress}".to_string(),
+            ..DisplayConfig::default()
+        };
+        let format = TrackFormat::from_config(&config);
+        let mut track = track("Song", &["Artist"]);
+
+        assert_eq!(
+            format.render(&track, None, Duration::from_secs(67)),
+            "1:07 1:07/3:00"
+        );
+
+        track.duration = chrono::Duration::seconds(4000);
+        assert_eq!(
+            format.render(&track, None, Duration::from_secs(3727)),
+            "1:02:07 1:02:07/1:06:40"
+        );
+    }
+
+    #[test]
+    fn blank_lines_are_dropped_or_replaced() {
+        let lines = vec![
+            line("first", 0, 1000),
+            line("  ", 1000, 2000),
+            line("", 2000, 3000),
+            line("second", 3000, 4000),
+        ];
+
+        let dropped = prepare_lines(lines.clone(), None, None, false);
+        assert_eq!(texts(&dropped), ["first", "second"]);
+
+        let replaced = prepare_lines(lines, None, Some("♪"), false);
+        assert_eq!(texts(&replaced), ["first", "♪", "♪", "second"]);
+    }
+
+    #[test]
+    fn tracks_without_a_name_show_the_artists_or_a_placeholder() {
+        let mut config = DisplayConfig::default();
+        let track = track(" ", &["Artist"]);
+
+        assert_eq!(
+            TrackFormat::from_config(&config).render(&track, None, Duration::ZERO),
+            "Artist"
+        );
+
+        config.unknown_track = Some("Unknown Track".to_string());
+        assert_eq!(
+            TrackFormat::from_config(&config).render(&track, None, Duration::ZERO),
+            "Unknown Track - Artist"
+        );
+    }
+
+    /// Remembers what it was sent, `None` for clearing.
+    struct RecordingSink(Arc<Mutex<Vec<Option<String>>>>);
+
+    #[async_trait]
+    impl StatusSink for RecordingSink {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        async fn send_update(&mut self, text: &str, _emoji: &str) -> Result<bool, DyricsError> {
+            self.0.lock().unwrap().push(Some(text.to_string()));
+            Ok(true)
+        }
+
+        async fn clear_status(&mut self) -> Result<(), DyricsError> {
+            self.0.lock().unwrap().push(None);
+            Ok(())
+        }
+    }
+
+    /// Rejects every update with the error it was made with, remembering how
+    /// often it was cleared.
+    struct RejectingSink(fn() -> DyricsError, Arc<Mutex<u32>>);
+
+    #[async_trait]
+    impl StatusSink for RejectingSink {
+        fn name(&self) -> &str {
+            "rejecting"
+        }
+
+        async fn send_update(&mut self, _text: &str, _emoji: &str) -> Result<bool, DyricsError> {
+            Err((self.0)())
+        }
+
+        async fn clear_status(&mut self) -> Result<(), DyricsError> {
+            *self.1.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    /// Runs the status loop until its only sink, which fails with `error`,
+    /// is dropped. Returns how often it was cleared.
+    async fn drop_rejecting_sink(
+        error: fn() -> DyricsError,
+        on_invalid_token: InvalidTokenBehavior,
+    ) -> eyre::Result<u32> {
+        let mut config = ConfigBuilder::new(SpotifyConfig::new("id", "secret"))
+            .build()
+            .unwrap();
+        config.discord.on_invalid_token = on_invalid_token;
+        let playback = CurrentPlayback {
+            lyrics: None,
+            track: track("Song", &["Artist"]),
+            position: Duration::ZERO,
+            duration: Duration::from_secs(180),
+            context: None,
+            generation: 1,
+            synced_at: Instant::now(),
+        };
+
+        let cleared = Arc::new(Mutex::new(0));
+        // the loop ends once there are no sinks left
+        let (_stop, shutdown) = Shutdown::channel();
+        status_loop(
+            Arc::new(RwLock::new(Some(playback))),
+            vec![Box::new(RejectingSink(error, cleared.clone()))],
+            &config,
+            Recorder::default(),
+            shutdown,
+        )
+        .await?;
+
+        let cleared = *cleared.lock().unwrap();
+        Ok(cleared)
+    }
+
+    #[tokio::test]
+    async fn sinks_are_cleared_before_they_are_dropped_over_an_invalid_token() {
+        let invalid_token = || DyricsError::DiscordAuth(reqwest::StatusCode::UNAUTHORIZED);
+
+        let cleared = drop_rejecting_sink(invalid_token, InvalidTokenBehavior::Stop).await;
+
+        assert_eq!(cleared.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_webhook_only_drops_its_sink() {
+        let rejected = || DyricsError::WebhookRejected(reqwest::StatusCode::NOT_FOUND);
+
+        let cleared = drop_rejecting_sink(rejected, InvalidTokenBehavior::Exit).await;
+
+        // nothing to clear on a webhook that is gone
+        assert_eq!(cleared.unwrap(), 0);
+    }
+
+    /// Runs the status loop once with playback right where the last line ends.
+    async fn join_at_the_end(past_lyrics: PastLyricsBehavior) -> Vec<Option<String>> {
+        let mut config = ConfigBuilder::new(SpotifyConfig::new("id", "secret"))
+            .build()
+            .unwrap();
+        config.display.past_lyrics = past_lyrics;
+        config.display.title_on_track_change = false;
+
+        let duration = Duration::from_secs(180);
+        let lyrics = Lyrics::from_lrc("[00:00.00] the only line\n[00:02.00]", duration);
+        let playback = CurrentPlayback {
+            lyrics,
+            track: track("Song", &["Artist"]),
+            position: Duration::from_secs(2),
+            duration,
+            context: None,
+            generation: 1,
+            synced_at: Instant::now(),
+        };
+
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let (stop, shutdown) = Shutdown::channel();
+        // the loop goes around once and then clears on the way out
+        stop.send(true).unwrap();
+        status_loop(
+            Arc::new(RwLock::new(Some(playback))),
+            vec![Box::new(RecordingSink(sent.clone()))],
+            &config,
+            Recorder::default(),
+            shutdown,
+        )
+        .await
+        .unwrap();
+
+        let sent = sent.lock().unwrap().clone();
+        sent
+    }
+
+    #[tokio::test]
+    async fn joining_after_the_last_line_follows_past_lyrics() {
+        assert_eq!(
+            join_at_the_end(PastLyricsBehavior::Title).await,
+            [Some("Song - Artist".to_string()), None]
+        );
+        assert_eq!(
+            join_at_the_end(PastLyricsBehavior::Clear).await,
+            [None, None]
+        );
+    }
+}
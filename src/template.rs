@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use crate::config::DurationFormat;
+
+/// Fills in the `{placeholders}` in `template`. Text in `[...]` is dropped if
+/// any placeholder in it is empty, e.g. `{name}[ - {artists}]` leaves out the
+/// dash for tracks without artists. Unknown placeholders are kept as they are.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut output = String::new();
+    // text of the optional section we're in, and whether it is still complete
+    let mut section: Option<(String, bool)> = None;
+    let mut rest = template;
+
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+
+        let (text, empty) = match c {
+            '[' if section.is_none() => {
+                section = Some((String::new(), true));
+                continue;
+            }
+            ']' if section.is_some() => {
+                if let Some((text, true)) = section.take() {
+                    output.push_str(&text);
+                }
+                continue;
+            }
+            '{' => match rest.split_once('}') {
+                Some((name, after)) => {
+                    rest = after;
+                    match values.iter().find(|(key, _)| *key == name) {
+                        Some((_, value)) => (value.to_string(), value.is_empty()),
+                        None => (format!("{{{}}}", name), false),
+                    }
+                }
+                None => (c.to_string(), false),
+            },
+            c => (c.to_string(), false),
+        };
+
+        match section {
+            Some((ref mut section_text, ref mut complete)) => {
+                section_text.push_str(&text);
+                *complete &= !empty;
+            }
+            None => output.push_str(&text),
+        }
+    }
+
+    // an unclosed bracket is just text
+    if let Some((text, _)) = section {
+        output.push('[');
+        output.push_str(&text);
+    }
+
+    output
+}
+
+/// Writes `duration` in whole seconds, rounded down.
+pub fn format_duration(duration: Duration, format: DurationFormat) -> String {
+    let total = duration.as_secs();
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+
+    match format {
+        DurationFormat::Seconds => format!("{}s", total),
+        DurationFormat::Clock if hours > 0 => format!("{}:{:02}:{:02}", hours, minutes, seconds),
+        DurationFormat::Clock => format!("{}:{:02}", minutes, seconds),
+        DurationFormat::PaddedClock if hours > 0 => {
+            format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+        }
+        DurationFormat::PaddedClock => format!("{:02}:{:02}", minutes, seconds),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_durations_under_a_minute_minutes_and_hours() {
+        let format = |secs: f64, format| format_duration(Duration::from_secs_f64(secs), format);
+
+        assert_eq!(format(7.9, DurationFormat::Clock), "0:07");
+        assert_eq!(format(187.0, DurationFormat::Clock), "3:07");
+        assert_eq!(format(3727.0, DurationFormat::Clock), "1:02:07");
+        assert_eq!(format(7.9, DurationFormat::PaddedClock), "00:07");
+        assert_eq!(format(187.0, DurationFormat::PaddedClock), "03:07");
+        assert_eq!(format(3727.0, DurationFormat::PaddedClock), "01:02:07");
+        assert_eq!(format(7.9, DurationFormat::Seconds), "7s");
+        assert_eq!(format(3727.0, DurationFormat::Seconds), "3727s");
+    }
+}
@@ -0,0 +1,154 @@
+//! Mock servers and fixtures shared by the tests.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+};
+
+use rspotify::model::FullTrack;
+
+use crate::lyrics::TimedLine;
+
+/// The requests a mock server got, in full.
+pub type Requests = Arc<Mutex<Vec<String>>>;
+
+/// A JSON response with `status`, the extra `headers` and `body`.
+pub fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\ncontent-type: application/json\r\n{}\
+         content-length: {}\r\nconnection: close\r\n\r\n",
+        status,
+        headers,
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+/// Answers every request with what `respond` makes of it, after `delay`.
+/// Requests it returns `None` for are taken but never answered. Returns the
+/// server's base url, like `http://127.0.0.1:1234`, and the requests it got.
+pub async fn serve_after<F>(delay: Duration, respond: F) -> (String, Requests)
+where
+    F: Fn(&str) -> Option<Vec<u8>> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let requests = Requests::default();
+    let received = requests.clone();
+    let respond = Arc::new(respond);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let received = received.clone();
+            let respond = respond.clone();
+            // one slow or unanswered request holds up none of the others
+            tokio::spawn(async move {
+                let request = read_request(&mut stream).await;
+                received.lock().unwrap().push(request.clone());
+                let Some(response) = respond(&request) else {
+                    std::future::pending::<()>().await;
+                    return;
+                };
+                tokio::time::sleep(delay).await;
+                let _ = stream.write_all(&response).await;
+            });
+        }
+    });
+    (format!("http://{}", address), requests)
+}
+
+/// Like [`serve_after`], without a delay.
+pub async fn serve_with<F>(respond: F) -> (String, Requests)
+where
+    F: Fn(&str) -> Option<Vec<u8>> + Send + Sync + 'static,
+{
+    serve_after(Duration::ZERO, respond).await
+}
+
+/// Answers every request with `status`, the extra `headers` and `body`.
+pub async fn serve(
+    status: &'static str,
+    headers: &'static str,
+    body: &'static str,
+) -> (String, Requests) {
+    serve_with(move |_| Some(response(status, headers, body.as_bytes()))).await
+}
+
+/// Takes requests but never answers them.
+pub async fn serve_nothing() -> String {
+    serve_with(|_| None).await.0
+}
+
+/// Reads a request up to the end of its body.
+async fn read_request(stream: &mut TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let read = stream.read(&mut buffer).await.unwrap_or(0);
+        request.extend_from_slice(&buffer[..read]);
+        let text = String::from_utf8_lossy(&request);
+        let Some((head, body)) = text.split_once("\r\n\r\n") else {
+            if read == 0 {
+                return text.into_owned();
+            }
+            continue;
+        };
+        let length = head
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().ok())?
+            })
+            .unwrap_or(0);
+        if body.len() >= length || read == 0 {
+            return text.into_owned();
+        }
+    }
+}
+
+/// A vocal line from `start` to `end` milliseconds.
+pub fn line(text: &str, start: u64, end: u64) -> TimedLine {
+    TimedLine {
+        text: text.to_string(),
+        start_time: Duration::from_millis(start),
+        end_time: Duration::from_millis(end),
+        word_starts: Vec::new(),
+        section: None,
+        line_type: "Vocal".to_string(),
+        opposite_aligned: false,
+        romanized: None,
+        translated: None,
+    }
+}
+
+/// A track called "Song" with the Spotify id `id`.
+pub fn track(id: &str) -> FullTrack {
+    serde_json::from_value(serde_json::json!({
+        "album": { "name": "Album", "artists": [], "external_urls": {}, "images": [] },
+        "artists": [],
+        "disc_number": 1,
+        "duration_ms": 180000,
+        "explicit": false,
+        "external_ids": {},
+        "external_urls": {},
+        "id": id,
+        "is_local": false,
+        "name": "Song",
+        "popularity": 0,
+        "track_number": 1
+    }))
+    .unwrap()
+}
+
+/// Lyrics synced by line, the way the lyrics provider sends them.
+pub const LINE_LYRICS: &str = r#"{"StartTime":0.0,"EndTime":1.0,"Type":"Line","Content":[
+    {"Type":"Vocal","OppositeAligned":false,"Text":"a line","StartTime":0.0,"EndTime":1.0}
+]}"#;
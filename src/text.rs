@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+/// Discord refuses custom statuses longer than this many characters.
+pub const MAX_STATUS_LEN: usize = 128;
+
+/// Whether `c` belongs to a script that is written without spaces between
+/// words, like Chinese, Japanese or Thai. Korean uses spaces, so it isn't one.
+pub fn is_unspaced_script(c: char) -> bool {
+    matches!(c,
+        '\u{0E00}'..='\u{0EFF}' // Thai, Lao
+        | '\u{1000}'..='\u{109F}' // Myanmar
+        | '\u{1780}'..='\u{17FF}' // Khmer
+        | '\u{3000}'..='\u{303F}' // CJK punctuation
+        | '\u{3040}'..='\u{30FF}' // Hiragana, Katakana
+        | '\u{31F0}'..='\u{31FF}' // Katakana extensions
+        | '\u{3400}'..='\u{4DBF}' // CJK extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK ideographs
+        | '\u{F900}'..='\u{FAFF}' // CJK compatibility ideographs
+        | '\u{FF00}'..='\u{FFEF}' // fullwidth and halfwidth forms
+        | '\u{20000}'..='\u{2FA1F}' // CJK extensions B and up
+    )
+}
+
+/// Whether `c` belongs to a script that is written right to left, like
+/// Hebrew or Arabic.
+pub fn is_rtl_script(c: char) -> bool {
+    matches!(c,
+        '\u{0590}'..='\u{08FF}' // Hebrew, Arabic, Syriac, Thaana, NKo and others
+        | '\u{FB1D}'..='\u{FDFF}' // Hebrew and Arabic presentation forms A
+        | '\u{FE70}'..='\u{FEFF}' // Arabic presentation forms B
+        | '\u{10800}'..='\u{10FFF}' // historic right to left scripts
+        | '\u{1E800}'..='\u{1EFFF}' // Adlam and others
+    )
+}
+
+/// Wraps lines that start out right to left in a right-to-left isolate, so
+/// they keep their direction next to left to right text and don't pull it
+/// around. Other lines are left alone.
+pub fn isolate_rtl(text: &str) -> String {
+    // the first letter decides, like it does for a paragraph
+    let rtl = text
+        .chars()
+        .find(|c| c.is_alphabetic())
+        .is_some_and(is_rtl_script);
+    if rtl {
+        format!("\u{2067}{}\u{2069}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Trims `text` and collapses runs of whitespace into single spaces.
+pub fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Shortens `text` to at most `max_len` characters, marking the cut with an
+/// ellipsis.
+pub fn truncate(text: &str, max_len: usize) -> String {
+    if text.chars().count() <= max_len {
+        return text.to_string();
+    }
+
+    let mut truncated = text
+        .chars()
+        .take(max_len.saturating_sub(1))
+        .collect::<String>()
+        .trim_end()
+        .to_string();
+    truncated.push('…');
+    truncated
+}
+
+/// Splits `text` into chunks of at most `width` characters without breaking
+/// words. Each chunk comes with the time of its first word, taken from
+/// `word_times`.
+pub fn split_at_words(
+    text: &str,
+    word_times: &[Duration],
+    width: usize,
+) -> Vec<(Duration, String)> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_time = Duration::ZERO;
+
+    for (word, time) in text.split_whitespace().zip(word_times) {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            chunks.push((current_time, truncate(&current, width)));
+            current.clear();
+        }
+
+        if current.is_empty() {
+            current_time = *time;
+        } else {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        chunks.push((current_time, truncate(&current, width)));
+    }
+
+    chunks
+}
+
+/// Splits a line that is wider than `width` into frames that scroll across
+/// it `step` characters at a time. At most `max_frames` frames are produced,
+/// stepping further if the line wouldn't fit otherwise.
+pub fn scroll_frames(text: &str, width: usize, step: usize, max_frames: usize) -> Vec<String> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let width = width.max(1);
+
+    if chars.len() <= width {
+        return vec![text.to_string()];
+    }
+    if max_frames <= 1 {
+        return vec![truncate(text, width)];
+    }
+
+    let overflow = chars.len() - width;
+    let step = step.max(1).max(overflow.div_ceil(max_frames - 1));
+
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    loop {
+        let offset_clamped = offset.min(overflow);
+        frames.push(
+            chars[offset_clamped..offset_clamped + width]
+                .iter()
+                .collect(),
+        );
+        if offset_clamped == overflow {
+            break;
+        }
+        offset += step;
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_right_to_left_lines_are_isolated() {
+        assert_eq!(isolate_rtl("a line"), "a line");
+        assert_eq!(isolate_rtl("123 a line"), "123 a line");
+        assert_eq!(isolate_rtl("שלום"), "\u{2067}שלום\u{2069}");
+        assert_eq!(isolate_rtl("مرحبا"), "\u{2067}مرحبا\u{2069}");
+        // the first letter decides, not the numbers before it
+        assert_eq!(
+            isolate_rtl("1. שלום world"),
+            "\u{2067}1. שלום world\u{2069}"
+        );
+        assert_eq!(isolate_rtl("hello שלום"), "hello שלום");
+    }
+}
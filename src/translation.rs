@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    config::TranslationConfig,
+    lyrics::{Lyrics, LyricsContent},
+};
+
+#[derive(Debug, Serialize)]
+struct TranslateRequest<'a> {
+    q: &'a [String],
+    source: &'static str,
+    target: &'a str,
+    format: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TranslateResponse {
+    translated_text: Vec<String>,
+}
+
+/// Translates lyrics through a LibreTranslate compatible API.
+pub struct Translator {
+    client: Client,
+    url: String,
+    target: String,
+    api_key: Option<String>,
+    /// Translations by the line they are of, `None` for lines that are
+    /// already in the target language
+    cache: RwLock<HashMap<String, Option<String>>>,
+}
+
+impl Translator {
+    pub fn new(client: Client, config: &TranslationConfig) -> Self {
+        Self {
+            client,
+            url: config.url.clone(),
+            target: config.target.clone(),
+            api_key: config.api_key.clone(),
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Fills in the translation of every line, asking only for lines that
+    /// weren't translated before. Lyrics synced by syllable are left alone.
+    pub async fn translate(&self, lyrics: &mut Lyrics) -> eyre::Result<()> {
+        let LyricsContent::Line(ref mut lines) = lyrics.content else {
+            return Ok(());
+        };
+
+        let mut missing = Vec::new();
+        {
+            let cache = self.cache.read().await;
+            for line in lines.iter() {
+                let text = line.text.trim();
+                if !text.is_empty()
+                    && !cache.contains_key(text)
+                    && !missing.iter().any(|line| line == text)
+                {
+                    missing.push(text.to_string());
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            let response = self
+                .client
+                .post(&self.url)
+                .json(&TranslateRequest {
+                    q: &missing,
+                    source: "auto",
+                    target: &self.target,
+                    format: "text",
+                    api_key: self.api_key.as_deref(),
+                })
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<TranslateResponse>()
+                .await?;
+            if response.translated_text.len() != missing.len() {
+                eyre::bail!(
+                    "asked to translate {} lines, but got {} back",
+                    missing.len(),
+                    response.translated_text.len()
+                );
+            }
+
+            let mut cache = self.cache.write().await;
+            for (text, translated) in missing.into_iter().zip(response.translated_text) {
+                let translated = translated.trim();
+                // a line in the target language would only be shown twice
+                let translated = (!translated.is_empty()
+                    && !translated.eq_ignore_ascii_case(&text))
+                .then(|| translated.to_string());
+                cache.insert(text, translated);
+            }
+        }
+
+        let cache = self.cache.read().await;
+        for line in lines.iter_mut() {
+            line.translated_text = cache.get(line.text.trim()).cloned().flatten();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::Instant;
+
+use async_trait::async_trait;
+use reqwest::{Client, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    config::WebhookConfig,
+    error::DyricsError,
+    playback::CurrentPlayback,
+    sink::{prepend_emoji, RequestWindow, StatusSink},
+};
+
+#[derive(Debug, Deserialize)]
+struct WebhookMessage {
+    id: String,
+}
+
+/// Turns error statuses into errors, telling a webhook that was deleted or
+/// never existed apart.
+fn check_status(response: Response) -> Result<Response, DyricsError> {
+    match response.status() {
+        status @ (StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND) => {
+            Err(DyricsError::WebhookRejected(status))
+        }
+        _ => response.error_for_status().map_err(DyricsError::Discord),
+    }
+}
+
+/// Posts the lyrics to a Discord channel through a webhook. Every track gets
+/// a message of its own that is edited as the lines go by, and lines are
+/// skipped while it is out of capacity so the channel doesn't get flooded.
+pub struct WebhookSink {
+    client: Client,
+    url: String,
+    prepend_emoji: bool,
+    requests: RequestWindow,
+    /// The message of the current track, once it is posted
+    message_id: Option<String>,
+    generation: Option<u64>,
+    last_sent: Option<String>,
+}
+
+impl WebhookSink {
+    pub fn new(client: Client, config: &WebhookConfig) -> Self {
+        Self {
+            client,
+            url: config.url.trim_end_matches('/').to_string(),
+            prepend_emoji: config.prepend_emoji,
+            requests: RequestWindow::new(config.rate_limit, config.rate_limit_window),
+            message_id: None,
+            generation: None,
+            last_sent: None,
+        }
+    }
+
+    async fn post(&self, content: &str) -> Result<String, DyricsError> {
+        let response = self
+            .client
+            .post(&self.url)
+            .query(&[("wait", "true")])
+            .json(&json!({ "content": content, "allowed_mentions": { "parse": [] } }))
+            .send()
+            .await
+            .map_err(DyricsError::Discord)?;
+
+        let message = check_status(response)?
+            .json::<WebhookMessage>()
+            .await
+            .map_err(DyricsError::Discord)?;
+        Ok(message.id)
+    }
+
+    /// Returns `false` if the message is gone, e.g. because it was deleted.
+    async fn edit(&self, message_id: &str, content: &str) -> Result<bool, DyricsError> {
+        let response = self
+            .client
+            .patch(format!("{}/messages/{}", self.url, message_id))
+            .json(&json!({ "content": content }))
+            .send()
+            .await
+            .map_err(DyricsError::Discord)?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        check_status(response)?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl StatusSink for WebhookSink {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn set_playback(&mut self, playback: &CurrentPlayback) {
+        // a new track starts a new message, seeks keep editing the old one
+        if self.generation != Some(playback.generation) {
+            self.generation = Some(playback.generation);
+            self.message_id = None;
+        }
+    }
+
+    async fn send_update(&mut self, text: &str, emoji: &str) -> Result<bool, DyricsError> {
+        if (self.message_id.is_some() && self.last_sent.as_deref() == Some(text))
+            || !self.requests.has_capacity()
+        {
+            return Ok(false);
+        }
+
+        let content = if self.prepend_emoji {
+            prepend_emoji(text, emoji)
+        } else {
+            text.to_string()
+        };
+        self.requests.record(Instant::now());
+        let edited = match self.message_id {
+            Some(ref message_id) => self.edit(message_id, &content).await?,
+            None => false,
+        };
+        if !edited {
+            self.message_id = Some(self.post(&content).await?);
+        }
+        self.last_sent = Some(text.to_string());
+
+        Ok(true)
+    }
+
+    async fn clear_status(&mut self) -> Result<(), DyricsError> {
+        // the messages stay in the channel, the next track gets a new one
+        self.message_id = None;
+        self.last_sent = None;
+        Ok(())
+    }
+}